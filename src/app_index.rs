@@ -0,0 +1,335 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::apps::{
+    AppEntry, Session, application_dirs, desktop_files, parse_desktop_file, resolve_apps,
+};
+
+// Bumped whenever AppEntry or the index layout changes so stale caches are
+// rebuilt instead of failing to deserialize halfway.
+const INDEX_VERSION: u32 = 2;
+
+// Parsed desktop files cached in $XDG_CACHE_HOME/bitpop/apps.json so the popup
+// can show apps without reading every .desktop file first. Files are keyed by
+// path and mtime: a rescan only re-parses what changed since the last one.
+// Parsing depends on the locale and current desktop, so an index built for a
+// different session is thrown away rather than reused.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct AppIndex {
+    version: u32,
+    locales: Vec<String>,
+    desktops: Vec<String>,
+    files: Vec<IndexedFile>,
+}
+
+// Files that parse to no app (hidden, NoDisplay, other desktops) are kept too,
+// since they still shadow entries with the same ID further down the path.
+#[derive(Clone, Serialize, Deserialize)]
+struct IndexedFile {
+    id: String,
+    path: PathBuf,
+    mtime: u64,
+    app: Option<AppEntry>,
+}
+
+impl AppIndex {
+    pub fn load() -> Option<AppIndex> {
+        AppIndex::load_from(&index_path()?, &Session::current())
+    }
+
+    fn load_from(path: &Path, session: &Session) -> Option<AppIndex> {
+        let content = fs::read(path).ok()?;
+        let index: AppIndex = serde_json::from_slice(&content).ok()?;
+        index.matches(session).then_some(index)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        self.save_to(&index_path().ok_or(io::ErrorKind::NotFound)?)
+    }
+
+    fn save_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = serde_json::to_vec(self).map_err(io::Error::other)?;
+
+        // Write-then-rename so a crash mid-write can't leave a truncated index.
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(tmp_path, path)
+    }
+
+    // Walks the application directories, reusing entries from `previous` whose
+    // file hasn't been modified since it was indexed. `on_progress` sees the
+    // index after each directory; since earlier directories take precedence,
+    // its apps are already final, just not complete yet.
+    pub fn scan(previous: Option<&AppIndex>, on_progress: impl FnMut(&AppIndex)) -> AppIndex {
+        AppIndex::scan_dirs(
+            &application_dirs(),
+            &Session::current(),
+            previous,
+            on_progress,
+        )
+    }
+
+    fn scan_dirs(
+        app_dirs: &[PathBuf],
+        session: &Session,
+        previous: Option<&AppIndex>,
+        mut on_progress: impl FnMut(&AppIndex),
+    ) -> AppIndex {
+        let cached: HashMap<(&Path, u64), &IndexedFile> = previous
+            .filter(|index| index.matches(session))
+            .map(|index| {
+                index
+                    .files
+                    .iter()
+                    .map(|file| ((file.path.as_path(), file.mtime), file))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut index = AppIndex {
+            version: INDEX_VERSION,
+            locales: session.locales.clone(),
+            desktops: session.desktops.clone(),
+            files: Vec::new(),
+        };
+
+        for dir in app_dirs {
+            for (id, path) in desktop_files(dir) {
+                let Some(mtime) = modified(&path) else {
+                    continue;
+                };
+                let app = match cached.get(&(path.as_path(), mtime)) {
+                    Some(file) if file.id == id => file.app.clone(),
+                    _ => parse_desktop_file(&path, &id, session),
+                };
+                index.files.push(IndexedFile {
+                    id,
+                    path,
+                    mtime,
+                    app,
+                });
+            }
+            on_progress(&index);
+        }
+
+        index
+    }
+
+    pub fn apps(&self) -> Vec<AppEntry> {
+        self.apps_for(&Session::current())
+    }
+
+    fn apps_for(&self, session: &Session) -> Vec<AppEntry> {
+        resolve_apps(
+            self.files
+                .iter()
+                .map(|file| (file.id.as_str(), file.app.as_ref())),
+            session,
+        )
+    }
+
+    // The directories to watch for changes: every application directory, even
+    // ones that don't exist yet, plus the subdirectories holding indexed files.
+    pub fn watched_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = application_dirs();
+        let mut seen: HashSet<PathBuf> = dirs.iter().cloned().collect();
+        for file in &self.files {
+            if let Some(parent) = file.path.parent()
+                && seen.insert(parent.to_path_buf())
+            {
+                dirs.push(parent.to_path_buf());
+            }
+        }
+        dirs
+    }
+
+    // Whether a rescan found the same files with the same mtimes, in which case
+    // there is nothing new to show or save.
+    pub fn same_files_as(&self, other: &AppIndex) -> bool {
+        self.version == other.version
+            && self.locales == other.locales
+            && self.desktops == other.desktops
+            && self.files.len() == other.files.len()
+            && self
+                .files
+                .iter()
+                .zip(&other.files)
+                .all(|(a, b)| a.id == b.id && a.path == b.path && a.mtime == b.mtime)
+    }
+
+    fn matches(&self, session: &Session) -> bool {
+        self.version == INDEX_VERSION
+            && self.locales == session.locales
+            && self.desktops == session.desktops
+    }
+}
+
+fn modified(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos() as u64)
+}
+
+fn index_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("bitpop").join("apps.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::File;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, SystemTime};
+
+    fn session(locales: &[&str]) -> Session {
+        Session {
+            locales: locales.iter().map(|l| l.to_string()).collect(),
+            desktops: Vec::new(),
+            path: vec![PathBuf::from("/bin"), PathBuf::from("/usr/bin")],
+        }
+    }
+
+    fn entry(name: &str, extra: &str) -> String {
+        format!(
+            "[Desktop Entry]\nType=Application\nName={}\nExec=run\n{}",
+            name, extra
+        )
+    }
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("bitpop-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn set_mtime(path: &Path, time: SystemTime) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn earlier_directories_shadow_later_ones_by_id() {
+        let root = temp_root("shadow");
+        let user = root.join("user");
+        let system = root.join("system");
+        fs::create_dir_all(&user).unwrap();
+        fs::create_dir_all(&system).unwrap();
+
+        fs::write(user.join("firefox.desktop"), entry("My Firefox", "")).unwrap();
+        fs::write(system.join("firefox.desktop"), entry("Firefox", "")).unwrap();
+        fs::write(user.join("games.desktop"), entry("Games", "Hidden=true\n")).unwrap();
+        fs::write(system.join("games.desktop"), entry("Games", "")).unwrap();
+        fs::write(system.join("htop.desktop"), entry("Monitor", "")).unwrap();
+        fs::write(system.join("gnome-monitor.desktop"), entry("Monitor", "")).unwrap();
+
+        let session = session(&[]);
+        let mut progress = Vec::new();
+        let index = AppIndex::scan_dirs(&[user, system], &session, None, |partial| {
+            progress.push(partial.apps_for(&session).len())
+        });
+        let apps = index.apps_for(&session);
+        fs::remove_dir_all(&root).unwrap();
+
+        let ids: Vec<&str> = apps.iter().map(|app| app.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec!["gnome-monitor.desktop", "htop.desktop", "firefox.desktop"]
+        );
+        assert_eq!(apps[2].name, "My Firefox");
+        assert_eq!(progress, vec![1, 3]);
+    }
+
+    #[test]
+    fn rescans_reuse_unchanged_files_and_reparse_modified_ones() {
+        let root = temp_root("rescan");
+        let file = root.join("editor.desktop");
+        let dirs = vec![root.clone()];
+        let session = session(&[]);
+        let names = |index: &AppIndex| -> Vec<String> {
+            index
+                .apps_for(&session)
+                .into_iter()
+                .map(|app| app.name)
+                .collect()
+        };
+
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        fs::write(&file, entry("Editor", "")).unwrap();
+        set_mtime(&file, time);
+        let first = AppIndex::scan_dirs(&dirs, &session, None, |_| {});
+
+        // Same mtime: the cached entry is trusted without reading the file.
+        fs::write(&file, entry("Renamed", "")).unwrap();
+        set_mtime(&file, time);
+        let second = AppIndex::scan_dirs(&dirs, &session, Some(&first), |_| {});
+        assert_eq!(names(&second), vec!["Editor"]);
+
+        set_mtime(&file, time + Duration::from_secs(1));
+        let third = AppIndex::scan_dirs(&dirs, &session, Some(&second), |_| {});
+        assert_eq!(names(&third), vec!["Renamed"]);
+
+        fs::write(root.join("added.desktop"), entry("Added", "")).unwrap();
+        fs::remove_file(&file).unwrap();
+        let fourth = AppIndex::scan_dirs(&dirs, &session, Some(&third), |_| {});
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(names(&fourth), vec!["Added"]);
+    }
+
+    #[test]
+    fn saved_index_is_only_loaded_for_the_same_session() {
+        let root = temp_root("index");
+        let apps_dir = root.join("applications");
+        fs::create_dir_all(&apps_dir).unwrap();
+        fs::write(apps_dir.join("editor.desktop"), entry("Editor", "")).unwrap();
+
+        let english = session(&["en_US", "en"]);
+        let index_file = root.join("cache/apps.json");
+        AppIndex::scan_dirs(&[apps_dir], &english, None, |_| {})
+            .save_to(&index_file)
+            .unwrap();
+
+        let loaded =
+            AppIndex::load_from(&index_file, &english).map(|index| index.apps_for(&english));
+        let german = AppIndex::load_from(&index_file, &session(&["de_DE", "de"]));
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(loaded.unwrap()[0].name, "Editor");
+        assert!(german.is_none());
+    }
+
+    #[test]
+    fn try_exec_is_checked_when_listing_not_when_indexing() {
+        let root = temp_root("tryexec");
+        fs::write(
+            root.join("tool.desktop"),
+            entry("Tool", "TryExec=bitpop-test-tool\n"),
+        )
+        .unwrap();
+
+        let mut session = session(&[]);
+        let index = AppIndex::scan_dirs(std::slice::from_ref(&root), &session, None, |_| {});
+        assert!(index.apps_for(&session).is_empty());
+
+        let bin = root.join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        let tool = bin.join("bitpop-test-tool");
+        fs::write(&tool, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        session.path.push(bin);
+        let apps = index.apps_for(&session);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(apps.len(), 1);
+    }
+}
@@ -0,0 +1,149 @@
+use gtk4::prelude::*;
+use gtk4::{gio, glib};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+use crate::app_index::AppIndex;
+use crate::apps::AppEntry;
+
+// Installing a package usually touches several desktop files in a row, so
+// rescans wait for the directories to settle.
+const RESCAN_DELAY: Duration = Duration::from_millis(500);
+
+// Loads the app list off the main thread and keeps it in sync with the
+// application directories. Scans run on a worker thread and stream app lists
+// back to `on_update`; once the first one finishes, every directory of the
+// index is watched with a GFileMonitor and changes trigger a rescan.
+pub struct AppWatcher {
+    index: RefCell<Option<AppIndex>>,
+    monitors: RefCell<Vec<gio::FileMonitor>>,
+    pending: RefCell<Option<glib::SourceId>>,
+    scanning: Cell<bool>,
+    dirty: Cell<bool>,
+    on_update: Box<dyn Fn(Vec<AppEntry>)>,
+}
+
+enum ScanUpdate {
+    Apps(Vec<AppEntry>),
+    Finished(AppIndex),
+}
+
+impl AppWatcher {
+    pub fn start(on_update: impl Fn(Vec<AppEntry>) + 'static) {
+        let watcher = Rc::new(AppWatcher {
+            index: RefCell::new(None),
+            monitors: RefCell::new(Vec::new()),
+            pending: RefCell::new(None),
+            scanning: Cell::new(false),
+            dirty: Cell::new(false),
+            on_update: Box::new(on_update),
+        });
+        watcher.rescan();
+    }
+
+    // The monitors' handlers own the watcher, so it lives as long as they do;
+    // replacing them after every scan picks up new subdirectories.
+    fn watch(self: &Rc<Self>) {
+        let dirs = match &*self.index.borrow() {
+            Some(index) => index.watched_dirs(),
+            None => return,
+        };
+        let mut monitors = Vec::new();
+
+        for dir in dirs {
+            let Ok(monitor) = gio::File::for_path(&dir)
+                .monitor_directory(gio::FileMonitorFlags::WATCH_MOVES, gio::Cancellable::NONE)
+            else {
+                continue;
+            };
+            let watcher = self.clone();
+            monitor.connect_changed(move |_, _, _, _| watcher.schedule_rescan());
+            monitors.push(monitor);
+        }
+
+        for old in self.monitors.replace(monitors) {
+            old.cancel();
+        }
+    }
+
+    fn schedule_rescan(self: &Rc<Self>) {
+        if let Some(source) = self.pending.take() {
+            source.remove();
+        }
+
+        let watcher = self.clone();
+        let source = glib::timeout_add_local_once(RESCAN_DELAY, move || {
+            watcher.pending.take();
+            watcher.rescan();
+        });
+        *self.pending.borrow_mut() = Some(source);
+    }
+
+    fn rescan(self: &Rc<Self>) {
+        // Changes that arrive mid-scan may have been missed; scan again after.
+        if self.scanning.replace(true) {
+            self.dirty.set(true);
+            return;
+        }
+
+        let (sender, receiver) = async_channel::unbounded();
+        let previous = self.index.borrow().clone();
+        thread::spawn(move || scan(previous, sender));
+
+        let watcher = self.clone();
+        glib::spawn_future_local(async move {
+            while let Ok(update) = receiver.recv().await {
+                match update {
+                    ScanUpdate::Apps(apps) => (watcher.on_update)(apps),
+                    ScanUpdate::Finished(index) => {
+                        *watcher.index.borrow_mut() = Some(index);
+                        watcher.watch();
+                    }
+                }
+            }
+
+            watcher.scanning.set(false);
+            if watcher.dirty.replace(false) {
+                watcher.rescan();
+            }
+        });
+    }
+}
+
+// Runs on the worker thread. The first scan has no index yet: it shows the
+// cached one straight away if there is one and revalidates it, otherwise it
+// streams apps directory by directory so the list fills in as it goes.
+fn scan(previous: Option<AppIndex>, sender: async_channel::Sender<ScanUpdate>) {
+    let send = |update| {
+        let _ = sender.send_blocking(update);
+    };
+
+    let (previous, stream) = match previous {
+        Some(index) => (Some(index), false),
+        None => {
+            let cached = AppIndex::load();
+            if let Some(index) = &cached {
+                send(ScanUpdate::Apps(index.apps()));
+            }
+            let stream = cached.is_none();
+            (cached, stream)
+        }
+    };
+
+    let index = AppIndex::scan(previous.as_ref(), |partial| {
+        if stream {
+            send(ScanUpdate::Apps(partial.apps()));
+        }
+    });
+
+    let changed = previous.is_none_or(|previous| !index.same_files_as(&previous));
+    if changed {
+        let _ = index.save();
+        if !stream {
+            send(ScanUpdate::Apps(index.apps()));
+        }
+    }
+    send(ScanUpdate::Finished(index));
+}
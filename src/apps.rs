@@ -1,38 +1,188 @@
 use dirs;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::env;
 use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
-#[derive(Clone)]
+use crate::desktop_entry::{KeyFile, user_locales};
+use crate::exec::{self, ExecContext};
+use crate::fuzzy;
+use crate::history::{self, History};
+use crate::terminal::wrap_in_terminal;
+
+#[derive(Clone, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub struct AppEntry {
+    pub id: String,
     pub name: String,
+    pub untranslated_name: String,
+    pub generic_name: Option<String>,
+    pub comment: Option<String>,
+    pub keywords: Vec<String>,
+    pub categories: Vec<String>,
     pub exec: String,
+    pub exec_name: String,
+    pub working_dir: Option<PathBuf>,
+    pub terminal: bool,
+    pub try_exec: Option<String>,
+    pub startup_wm_class: Option<String>,
     pub icon: String,
     pub desktop_file: PathBuf,
+    pub actions: Vec<AppAction>,
+}
+
+// A `[Desktop Action <id>]` group listed in the entry's `Actions` key, e.g.
+// "New Private Window".
+#[derive(Clone, Serialize, Deserialize)]
+#[allow(dead_code)]
+pub struct AppAction {
+    pub id: String,
+    pub name: String,
+    pub icon: Option<String>,
+    pub exec: String,
+}
+
+impl AppEntry {
+    // Fuzzy-scores this entry against `query`, or None if it doesn't match at
+    // all. Each field's score is scaled by how much it says about the app: the
+    // name (localized or untranslated) counts fully, then GenericName, Keywords,
+    // the Exec binary and Categories. Comments are long prose that fuzzy-match
+    // almost anything, so they only count on a plain substring match.
+    pub fn match_score(&self, query: &str) -> Option<i32> {
+        if query.is_empty() {
+            return Some(0);
+        }
+
+        let names = [&self.name, &self.untranslated_name]
+            .into_iter()
+            .map(|name| (100, name.as_str()));
+        let generic_name = self.generic_name.iter().map(|name| (70, name.as_str()));
+        let keywords = self.keywords.iter().map(|keyword| (60, keyword.as_str()));
+        let exec_name = std::iter::once((50, self.exec_name.as_str()));
+        let categories = self
+            .categories
+            .iter()
+            .map(|category| (40, category.as_str()));
+
+        let comment = self
+            .comment
+            .as_deref()
+            .filter(|comment| comment.to_lowercase().contains(query))
+            .and_then(|comment| fuzzy::score(comment, query))
+            .map(|score| score * 30 / 100);
+
+        names
+            .chain(generic_name)
+            .chain(keywords)
+            .chain(exec_name)
+            .chain(categories)
+            .filter_map(|(weight, text)| {
+                fuzzy::score(text, query).map(|score| score * weight / 100)
+            })
+            .chain(comment)
+            .max()
+    }
+}
+
+// How well `app` matches `query`, or None if it doesn't. Frequently and
+// recently launched apps get a boost, which alone decides the order when the
+// query is empty.
+pub fn search_score(app: &AppEntry, query: &str, history: &History, now: i64) -> Option<i32> {
+    Some(app.match_score(query)? + frecency_boost(history.frecency(&app.id, now)))
+}
+
+// Result order: best score first, ties alphabetical like the app list itself.
+pub fn compare_results(a: (i32, &AppEntry), b: (i32, &AppEntry)) -> Ordering {
+    b.0.cmp(&a.0)
+        .then_with(|| a.1.name.to_lowercase().cmp(&b.1.name.to_lowercase()))
 }
 
-pub fn load_desktop_apps() -> Vec<AppEntry> {
+// Grows with the log of the decayed launch count and is capped so a heavily
+// used app can't outrank a much better name match.
+fn frecency_boost(frecency: f64) -> i32 {
+    (frecency.ln_1p() * 12.0).min(48.0) as i32
+}
+
+// The parts of the user's environment that decide how entries are localized and
+// whether they are shown at all.
+pub struct Session {
+    pub locales: Vec<String>,
+    pub desktops: Vec<String>,
+    pub path: Vec<PathBuf>,
+}
+
+impl Session {
+    pub fn current() -> Session {
+        Session {
+            locales: user_locales(),
+            desktops: env::var("XDG_CURRENT_DESKTOP")
+                .unwrap_or_default()
+                .split(':')
+                .filter(|desktop| !desktop.is_empty())
+                .map(|desktop| desktop.to_string())
+                .collect(),
+            path: search_path(),
+        }
+    }
+
+    fn is_current_desktop(&self, desktop: &str) -> bool {
+        self.desktops
+            .iter()
+            .any(|current| current.eq_ignore_ascii_case(desktop))
+    }
+
+    // TryExec names a binary that must exist for the entry to be usable; it's
+    // how leftovers from uninstalled packages get filtered out. It's checked
+    // here rather than when parsing so cached entries notice installs too.
+    pub fn is_installed(&self, app: &AppEntry) -> bool {
+        app.try_exec
+            .as_deref()
+            .is_none_or(|try_exec| find_executable(try_exec, &self.path).is_some())
+    }
+}
+
+impl AppEntry {
+    // Whether a window with this Wayland app_id or X11 class belongs to the
+    // app: it's the entry's StartupWMClass if it has one, or else the desktop
+    // file ID (as GTK and Qt apps set it) or the name of the program. Entries
+    // that share a program, like a browser's web apps, tell their windows
+    // apart with StartupWMClass.
+    pub fn owns_window(&self, app_id: &str) -> bool {
+        if app_id.is_empty() {
+            return false;
+        }
+        match &self.startup_wm_class {
+            Some(class) => class.eq_ignore_ascii_case(app_id),
+            None => [self.id.trim_end_matches(".desktop"), &self.exec_name]
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(app_id)),
+        }
+    }
+}
+
+// Turns parsed desktop files, listed in directory precedence order, into the
+// apps to show, sorted by name. The first file with a given ID shadows the
+// rest, even when it is hidden or not displayable; that's how users mask
+// system entries.
+pub fn resolve_apps<'a>(
+    files: impl IntoIterator<Item = (&'a str, Option<&'a AppEntry>)>,
+    session: &Session,
+) -> Vec<AppEntry> {
     let mut apps = Vec::new();
-    let app_dirs = [
-        PathBuf::from("/usr/share/applications"),
-        PathBuf::from("/usr/local/share/applications"),
-        dirs::data_dir()
-            .map(|d| d.join("applications"))
-            .unwrap_or_default(),
-    ];
-
-    for dir in &app_dirs {
-        if let Ok(entries) = fs::read_dir(dir) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if path.extension().and_then(|s| s.to_str()) == Some("desktop") {
-                    if let Some(app) = parse_desktop_file(&path) {
-                        if !apps.iter().any(|a: &AppEntry| a.name == app.name) {
-                            apps.push(app);
-                        }
-                    }
-                }
-            }
+    let mut seen_ids = HashSet::new();
+
+    for (id, app) in files {
+        if !seen_ids.insert(id) {
+            continue;
+        }
+        if let Some(app) = app.filter(|app| session.is_installed(app)) {
+            apps.push(app.clone());
         }
     }
 
@@ -40,58 +190,631 @@ pub fn load_desktop_apps() -> Vec<AppEntry> {
     apps
 }
 
-fn parse_desktop_file(path: &Path) -> Option<AppEntry> {
+// `applications` directories in XDG precedence order: $XDG_DATA_HOME first, then
+// each entry of $XDG_DATA_DIRS. Flatpak, Snap and Nix export locations are added
+// after those when the session didn't already put them on XDG_DATA_DIRS.
+pub fn application_dirs() -> Vec<PathBuf> {
+    let mut data_dirs: Vec<PathBuf> = Vec::new();
+
+    if let Some(data_home) = dirs::data_dir() {
+        data_dirs.push(data_home);
+    }
+
+    let system_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    data_dirs.extend(
+        system_dirs
+            .split(':')
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute()),
+    );
+
+    if let Some(data_home) = dirs::data_dir() {
+        data_dirs.push(data_home.join("flatpak/exports/share"));
+    }
+    data_dirs.push(PathBuf::from("/var/lib/flatpak/exports/share"));
+    data_dirs.push(PathBuf::from("/var/lib/snapd/desktop"));
+    if let Some(home) = dirs::home_dir() {
+        data_dirs.push(home.join(".nix-profile/share"));
+    }
+    if let Ok(user) = env::var("USER") {
+        data_dirs.push(PathBuf::from(format!(
+            "/etc/profiles/per-user/{}/share",
+            user
+        )));
+    }
+    data_dirs.push(PathBuf::from("/run/current-system/sw/share"));
+
+    let mut app_dirs: Vec<PathBuf> = Vec::new();
+    for dir in data_dirs {
+        let dir = dir.join("applications");
+        if !app_dirs.contains(&dir) {
+            app_dirs.push(dir);
+        }
+    }
+    app_dirs
+}
+
+// Every .desktop file below `dir`, paired with its desktop file ID: the path
+// relative to `dir` with `/` replaced by `-` (kde4/foo.desktop -> kde4-foo.desktop).
+pub fn desktop_files(dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    collect_desktop_files(dir, "", &mut visited, &mut files);
+    files.sort();
+    files
+}
+
+fn collect_desktop_files(
+    dir: &Path,
+    prefix: &str,
+    visited: &mut HashSet<PathBuf>,
+    files: &mut Vec<(String, PathBuf)>,
+) {
+    // Guards against symlink loops between subdirectories.
+    let Ok(canonical) = fs::canonicalize(dir) else {
+        return;
+    };
+    if !visited.insert(canonical) {
+        return;
+    }
+
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(file_name) = path.file_name().and_then(|s| s.to_str()) else {
+            continue;
+        };
+
+        if path.is_dir() {
+            let prefix = format!("{}{}-", prefix, file_name);
+            collect_desktop_files(&path, &prefix, visited, files);
+        } else if path.extension().and_then(|s| s.to_str()) == Some("desktop") {
+            files.push((format!("{}{}", prefix, file_name), path));
+        }
+    }
+}
+
+pub fn parse_desktop_file(path: &Path, id: &str, session: &Session) -> Option<AppEntry> {
     let content = fs::read_to_string(path).ok()?;
+    app_from_key_file(&KeyFile::parse(&content), path, id, session)
+}
+
+fn app_from_key_file(file: &KeyFile, path: &Path, id: &str, session: &Session) -> Option<AppEntry> {
+    let entry = file.group("Desktop Entry")?;
 
-    if !content.contains("[Desktop Entry]") {
+    if entry.string("Type").as_deref() != Some("Application") {
         return None;
     }
 
-    if content.contains("NoDisplay=true") {
+    if entry.boolean("Hidden") == Some(true) || entry.boolean("NoDisplay") == Some(true) {
         return None;
     }
 
-    if content.contains("Type=Application") {
-        let name = extract_field(&content, "Name").unwrap_or_default();
-        let exec = extract_field(&content, "Exec").unwrap_or_default();
-        let icon = extract_field(&content, "Icon")
-            .unwrap_or_else(|| "application-x-executable".to_string());
+    let only_show_in = entry.string_list("OnlyShowIn");
+    if !only_show_in.is_empty() && !only_show_in.iter().any(|d| session.is_current_desktop(d)) {
+        return None;
+    }
 
-        if !name.is_empty() && !exec.is_empty() {
-            return Some(AppEntry {
-                name,
-                exec,
-                icon,
-                desktop_file: path.to_path_buf(),
-            });
+    if entry
+        .string_list("NotShowIn")
+        .iter()
+        .any(|d| session.is_current_desktop(d))
+    {
+        return None;
+    }
+
+    let untranslated_name = entry.string("Name").unwrap_or_default();
+    let name = entry
+        .locale_string("Name", &session.locales)
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| untranslated_name.clone());
+    let generic_name = entry
+        .locale_string("GenericName", &session.locales)
+        .filter(|s| !s.is_empty());
+    let comment = entry
+        .locale_string("Comment", &session.locales)
+        .filter(|s| !s.is_empty());
+    let mut keywords = entry.locale_string_list("Keywords", &session.locales);
+    for keyword in entry.string_list("Keywords") {
+        if !keywords.contains(&keyword) {
+            keywords.push(keyword);
         }
     }
+    let categories = entry.string_list("Categories");
+    let exec = entry.string("Exec").unwrap_or_default();
+    let exec_name = exec_binary_name(&exec);
+    let working_dir = entry
+        .string("Path")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from);
+    let terminal = entry.boolean("Terminal").unwrap_or(false);
+    let try_exec = entry.string("TryExec").filter(|s| !s.is_empty());
+    let startup_wm_class = entry.string("StartupWMClass").filter(|s| !s.is_empty());
+    let icon = entry
+        .string("Icon")
+        .filter(|icon| !icon.is_empty())
+        .unwrap_or_else(|| "application-x-executable".to_string());
+
+    if untranslated_name.is_empty() || exec.is_empty() {
+        return None;
+    }
 
-    None
-}
-
-fn extract_field(content: &str, field: &str) -> Option<String> {
-    for line in content.lines() {
-        if line.starts_with(&format!("{}=", field)) {
-            let value = line.splitn(2, '=').nth(1)?;
-            let cleaned = value
-                .replace("%f", "")
-                .replace("%F", "")
-                .replace("%u", "")
-                .replace("%U", "")
-                .replace("%i", "")
-                .replace("%c", "")
-                .replace("%k", "")
-                .trim()
-                .to_string();
-            return Some(cleaned);
+    let actions = parse_actions(file, &entry.string_list("Actions"), &session.locales);
+
+    Some(AppEntry {
+        id: id.to_string(),
+        name,
+        untranslated_name,
+        generic_name,
+        comment,
+        keywords,
+        categories,
+        exec,
+        exec_name,
+        working_dir,
+        terminal,
+        try_exec,
+        startup_wm_class,
+        icon,
+        desktop_file: path.to_path_buf(),
+        actions,
+    })
+}
+
+// The program an Exec line runs, without its directory, looking past an `env`
+// wrapper and its VAR=value assignments.
+fn exec_binary_name(exec: &str) -> String {
+    let args = exec::tokenize(exec).unwrap_or_default();
+    let mut args = args.iter().map(String::as_str).peekable();
+
+    if args.peek().and_then(|arg| arg.rsplit('/').next()) == Some("env") {
+        args.next();
+        while args
+            .peek()
+            .is_some_and(|arg| arg.contains('=') || arg.starts_with('-'))
+        {
+            args.next();
         }
     }
-    None
+
+    args.next()
+        .and_then(|program| program.rsplit('/').next())
+        .unwrap_or_default()
+        .to_string()
 }
 
-pub fn launch_app(app: &AppEntry) {
-    let _ = std::process::Command::new("gtk-launch")
-        .arg(&app.desktop_file.file_name().unwrap_or_default())
-        .spawn();
+// Actions without a Name are invalid, and ones without an Exec can only be
+// activated over D-Bus, which we don't do; both are skipped.
+fn parse_actions(file: &KeyFile, ids: &[String], locales: &[String]) -> Vec<AppAction> {
+    ids.iter()
+        .filter_map(|id| {
+            let group = file.group(&format!("Desktop Action {}", id))?;
+            let name = group
+                .locale_string("Name", locales)
+                .filter(|s| !s.is_empty())?;
+            let exec = group.string("Exec").filter(|s| !s.is_empty())?;
+            let icon = group.string("Icon").filter(|s| !s.is_empty());
+
+            Some(AppAction {
+                id: id.clone(),
+                name,
+                icon,
+                exec,
+            })
+        })
+        .collect()
+}
+
+pub fn search_path() -> Vec<PathBuf> {
+    env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect())
+        .unwrap_or_default()
+}
+
+// Resolves `program` the way exec would: absolute paths are checked as-is,
+// bare names are looked up in each `path` directory.
+pub fn find_executable(program: &str, path: &[PathBuf]) -> Option<PathBuf> {
+    let candidate = Path::new(program);
+    if candidate.is_absolute() {
+        return is_executable(candidate).then(|| candidate.to_path_buf());
+    }
+    if program.contains('/') {
+        return None;
+    }
+
+    path.iter()
+        .map(|dir| dir.join(program))
+        .find(|candidate| is_executable(candidate))
+}
+
+pub fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+pub fn launch_app(app: &AppEntry) -> Result<(), String> {
+    launch_exec(app, &app.exec)
+}
+
+pub fn launch_action(app: &AppEntry, action: &AppAction) -> Result<(), String> {
+    launch_exec(app, &action.exec).map_err(|err| format!("{} ({})", err, action.name))
+}
+
+fn launch_exec(app: &AppEntry, exec: &str) -> Result<(), String> {
+    let desktop_file = app.desktop_file.to_string_lossy();
+    let context = ExecContext {
+        name: &app.name,
+        icon: Some(&app.icon),
+        desktop_file: &desktop_file,
+    };
+
+    let mut argv = exec::expand(exec, &context)
+        .map_err(|err| format!("Couldn't launch {}: {}", app.name, err))?;
+
+    if app.terminal {
+        argv = wrap_in_terminal(&argv)
+            .map_err(|err| format!("Couldn't launch {}: {}", app.name, err))?;
+    }
+
+    spawn_detached(&argv, app.working_dir.as_deref())
+        .map_err(|err| format!("Couldn't launch {}: {}", app.name, err))?;
+
+    history::record_launch(&app.id);
+    Ok(())
+}
+
+// Starts the program in its own session so it outlives BitPop closing. Exec
+// failures (missing binary, no permission) are reported by spawn itself.
+pub fn spawn_detached(argv: &[String], working_dir: Option<&Path>) -> io::Result<()> {
+    let mut command = Command::new(&argv[0]);
+    command.args(&argv[1..]).stdin(Stdio::null());
+
+    if let Some(dir) = working_dir {
+        command.current_dir(dir);
+    }
+
+    unsafe {
+        command.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+
+    command.spawn().map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search_apps<'a>(apps: &'a [AppEntry], query: &str, history: &History) -> Vec<&'a AppEntry> {
+        let now = history::now();
+        let mut scored: Vec<(i32, &AppEntry)> = apps
+            .iter()
+            .filter_map(|app| Some((search_score(app, query, history, now)?, app)))
+            .collect();
+        scored.sort_by(|a, b| compare_results(*a, *b));
+        scored.into_iter().map(|(_, app)| app).collect()
+    }
+
+    fn session(locales: &[&str], desktops: &[&str]) -> Session {
+        Session {
+            locales: locales.iter().map(|l| l.to_string()).collect(),
+            desktops: desktops.iter().map(|d| d.to_string()).collect(),
+            path: vec![PathBuf::from("/bin"), PathBuf::from("/usr/bin")],
+        }
+    }
+
+    fn parse(content: &str) -> Option<AppEntry> {
+        parse_with(content, &session(&[], &[]))
+    }
+
+    fn parse_localized(content: &str, locales: &[&str]) -> Option<AppEntry> {
+        parse_with(content, &session(locales, &[]))
+    }
+
+    fn parse_with(content: &str, session: &Session) -> Option<AppEntry> {
+        app_from_key_file(
+            &KeyFile::parse(content),
+            Path::new("/tmp/test.desktop"),
+            "test.desktop",
+            session,
+        )
+        .filter(|app| session.is_installed(app))
+    }
+
+    #[test]
+    fn reads_fields_from_desktop_entry_group() {
+        let app = parse(
+            "[Desktop Entry]\nType=Application\nName=Firefox\nExec=firefox %u\nIcon=firefox\n",
+        )
+        .unwrap();
+        assert_eq!(app.name, "Firefox");
+        assert_eq!(app.exec, "firefox %u");
+        assert_eq!(app.icon, "firefox");
+        assert!(!app.terminal);
+    }
+
+    #[test]
+    fn action_groups_do_not_leak_into_the_main_entry() {
+        let app = parse(
+            "[Desktop Action private]\nName=New Private Window\nExec=firefox --private-window\n\
+             NoDisplay=true\n\n\
+             [Desktop Entry]\nType=Application\nName=Firefox\nExec=firefox %u\n",
+        )
+        .unwrap();
+        assert_eq!(app.name, "Firefox");
+        assert_eq!(app.exec, "firefox %u");
+    }
+
+    #[test]
+    fn actions_are_read_in_declared_order() {
+        let app = parse_localized(
+            "[Desktop Entry]\nType=Application\nName=Firefox\nExec=firefox %u\n\
+             Actions=private;new-window;no-exec;missing;\n\n\
+             [Desktop Action new-window]\nName=New Window\nName[de]=Neues Fenster\n\
+             Exec=firefox --new-window %u\n\n\
+             [Desktop Action private]\nName=New Private Window\nIcon=private\n\
+             Exec=firefox --private-window %u\n\n\
+             [Desktop Action no-exec]\nName=D-Bus only\n\n\
+             [Desktop Action unlisted]\nName=Unlisted\nExec=firefox\n",
+            &["de"],
+        )
+        .unwrap();
+
+        let actions: Vec<(&str, &str)> = app
+            .actions
+            .iter()
+            .map(|a| (a.id.as_str(), a.name.as_str()))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                ("private", "New Private Window"),
+                ("new-window", "Neues Fenster")
+            ]
+        );
+        assert_eq!(app.actions[0].icon.as_deref(), Some("private"));
+        assert_eq!(app.actions[0].exec, "firefox --private-window %u");
+    }
+
+    #[test]
+    fn windows_are_matched_by_wm_class_or_else_desktop_id_or_program() {
+        let app = parse(
+            "[Desktop Entry]\nType=Application\nName=Code\nExec=/usr/bin/code %F\n\
+             StartupWMClass=Code-OSS\n",
+        )
+        .unwrap();
+        assert_eq!(app.startup_wm_class.as_deref(), Some("Code-OSS"));
+        assert!(app.owns_window("Code-OSS"));
+        assert!(app.owns_window("code-oss"));
+        assert!(!app.owns_window("codium"));
+        assert!(!app.owns_window(""));
+        // The class alone decides when the entry has one
+        assert!(!app.owns_window("test"));
+        assert!(!app.owns_window("code"));
+
+        let app =
+            parse("[Desktop Entry]\nType=Application\nName=Code\nExec=/usr/bin/code %F\n").unwrap();
+        assert!(app.owns_window("test"));
+        assert!(app.owns_window("Code"));
+        assert!(!app.owns_window("codium"));
+        assert!(!app.owns_window(""));
+    }
+
+    #[test]
+    fn terminal_flag_is_read() {
+        let app = parse("[Desktop Entry]\nType=Application\nName=htop\nExec=htop\nTerminal=true\n")
+            .unwrap();
+        assert!(app.terminal);
+    }
+
+    #[test]
+    fn search_covers_secondary_fields_and_ranks_names_first() {
+        let firefox = parse(
+            "[Desktop Entry]\nType=Application\nName=Firefox\nGenericName=Web Browser\n\
+             Keywords=internet;www;\nCategories=Network;WebBrowser;\nExec=/usr/lib/firefox/firefox %u\n",
+        )
+        .unwrap();
+        let calc = parse(
+            "[Desktop Entry]\nType=Application\nName=Calculator\nComment=Perform calculations\n\
+             Exec=env GDK_BACKEND=x11 gnome-calculator\n",
+        )
+        .unwrap();
+        let browser =
+            parse("[Desktop Entry]\nType=Application\nName=Browser Tools\nExec=btools\n").unwrap();
+        assert_eq!(firefox.exec_name, "firefox");
+        assert_eq!(calc.exec_name, "gnome-calculator");
+
+        assert!(firefox.match_score("www").is_some());
+        assert!(calc.match_score("gnome-calc").is_some());
+        assert!(calc.match_score("calculations").is_some());
+        assert!(firefox.match_score("network").is_some());
+        assert!(calc.match_score("browser").is_none());
+        assert!(firefox.match_score("ff").is_some());
+
+        let apps = vec![browser, calc, firefox];
+        let names: Vec<&str> = search_apps(&apps, "browser", &History::default())
+            .iter()
+            .map(|app| app.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Browser Tools", "Firefox"]);
+
+        let names: Vec<&str> = search_apps(&apps, "calc", &History::default())
+            .iter()
+            .map(|app| app.name.as_str())
+            .collect();
+        assert_eq!(names[0], "Calculator");
+        assert_eq!(search_apps(&apps, "", &History::default()).len(), 3);
+
+        let names: Vec<&str> = search_apps(&apps, "ff", &History::default())
+            .iter()
+            .map(|app| app.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Firefox"]);
+    }
+
+    #[test]
+    fn launch_history_orders_empty_query_and_boosts_matches() {
+        let entry = |name: &str| {
+            parse(&format!(
+                "[Desktop Entry]\nType=Application\nName={}\nExec=x\n",
+                name
+            ))
+            .unwrap()
+        };
+        let mut apps = vec![
+            entry("Terminal"),
+            entry("Text Editor"),
+            entry("Thunderbird"),
+        ];
+        apps[0].id = "terminal.desktop".to_string();
+        apps[1].id = "editor.desktop".to_string();
+        apps[2].id = "thunderbird.desktop".to_string();
+
+        let mut history = History::default();
+        let now = history::now();
+        history.record("thunderbird.desktop", now - 60);
+        history.record("editor.desktop", now - 3 * 24 * 60 * 60);
+        history.record("thunderbird.desktop", now);
+
+        let names = |query: &str| -> Vec<String> {
+            search_apps(&apps, query, &history)
+                .iter()
+                .map(|app| app.name.clone())
+                .collect()
+        };
+        assert_eq!(names(""), vec!["Thunderbird", "Text Editor", "Terminal"]);
+        assert_eq!(names("te")[0], "Text Editor");
+
+        history.forget("thunderbird.desktop");
+        assert_eq!(search_apps(&apps, "", &history)[0].name, "Text Editor");
+    }
+
+    #[test]
+    fn nodisplay_in_a_comment_is_ignored() {
+        let app = parse(
+            "[Desktop Entry]\n# NoDisplay=true\nType=Application\nName=Files\nExec=nautilus\n",
+        );
+        assert!(app.is_some());
+    }
+
+    #[test]
+    fn nodisplay_entries_are_skipped() {
+        let app =
+            parse("[Desktop Entry]\nType=Application\nName=Helper\nExec=helper\nNoDisplay=true\n");
+        assert!(app.is_none());
+    }
+
+    #[test]
+    fn hidden_entries_are_skipped() {
+        let app = parse("[Desktop Entry]\nType=Application\nName=Old\nExec=old\nHidden=true\n");
+        assert!(app.is_none());
+    }
+
+    #[test]
+    fn only_show_in_and_not_show_in_follow_current_desktop() {
+        let kde_only = "[Desktop Entry]\nType=Application\nName=K\nExec=k\nOnlyShowIn=KDE;\n";
+        let not_gnome = "[Desktop Entry]\nType=Application\nName=N\nExec=n\nNotShowIn=GNOME;\n";
+
+        let gnome = session(&[], &["ubuntu", "GNOME"]);
+        assert!(parse_with(kde_only, &gnome).is_none());
+        assert!(parse_with(not_gnome, &gnome).is_none());
+
+        let kde = session(&[], &["KDE"]);
+        assert!(parse_with(kde_only, &kde).is_some());
+        assert!(parse_with(not_gnome, &kde).is_some());
+
+        let unknown = session(&[], &[]);
+        assert!(parse_with(kde_only, &unknown).is_none());
+        assert!(parse_with(not_gnome, &unknown).is_some());
+    }
+
+    #[test]
+    fn try_exec_must_resolve_to_an_executable() {
+        let entry = |try_exec: &str| {
+            format!(
+                "[Desktop Entry]\nType=Application\nName=T\nExec=t\nTryExec={}\n",
+                try_exec
+            )
+        };
+        assert!(parse(&entry("sh")).is_some());
+        assert!(parse(&entry("/bin/sh")).is_some());
+        assert!(parse(&entry("definitely-not-installed-bitpop")).is_none());
+        assert!(parse(&entry("/nonexistent/bin/tool")).is_none());
+    }
+
+    #[test]
+    fn non_application_types_are_skipped() {
+        assert!(
+            parse("[Desktop Entry]\nType=Link\nName=Docs\nURL=https://example.com\n").is_none()
+        );
+        assert!(
+            parse("[Desktop Entry]\nType=Directory\nName=Games\nComment=Type=Application\n")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn missing_desktop_entry_group_is_rejected() {
+        assert!(parse("[Desktop Action new]\nType=Application\nName=X\nExec=x\n").is_none());
+    }
+
+    #[test]
+    fn missing_icon_falls_back_to_generic() {
+        let app =
+            parse("[Desktop Entry]\nType=Application\nName=Tool\nExec=tool\nIcon=\n").unwrap();
+        assert_eq!(app.icon, "application-x-executable");
+    }
+
+    #[test]
+    fn escaped_name_is_unescaped() {
+        let app = parse("[Desktop Entry]\nType=Application\nName=Foo\\sBar\nExec=foo\n").unwrap();
+        assert_eq!(app.name, "Foo Bar");
+    }
+
+    #[test]
+    fn localized_fields_are_resolved_and_untranslated_name_kept() {
+        let app = parse_localized(
+            "[Desktop Entry]\nType=Application\nName=Text Editor\nName[de]=Texteditor\n\
+             GenericName=Editor\nGenericName[de]=Bearbeiter\nComment=Edit text\nExec=gedit\n",
+            &["de_DE", "de"],
+        )
+        .unwrap();
+        assert_eq!(app.name, "Texteditor");
+        assert_eq!(app.untranslated_name, "Text Editor");
+        assert_eq!(app.generic_name.as_deref(), Some("Bearbeiter"));
+        assert_eq!(app.comment.as_deref(), Some("Edit text"));
+        assert!(app.match_score("texted").is_some());
+        assert!(app.match_score("text ed").is_some());
+    }
+
+    #[test]
+    fn desktop_file_ids_include_subdirectories() {
+        let root = env::temp_dir().join(format!("bitpop-ids-{}", std::process::id()));
+        fs::create_dir_all(root.join("kde4/extra")).unwrap();
+        fs::write(root.join("firefox.desktop"), "").unwrap();
+        fs::write(root.join("kde4/foo.desktop"), "").unwrap();
+        fs::write(root.join("kde4/extra/bar.desktop"), "").unwrap();
+        fs::write(root.join("kde4/readme.txt"), "").unwrap();
+
+        let ids: Vec<String> = desktop_files(&root).into_iter().map(|(id, _)| id).collect();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            ids,
+            vec![
+                "firefox.desktop",
+                "kde4-extra-bar.desktop",
+                "kde4-foo.desktop"
+            ]
+        );
+    }
 }
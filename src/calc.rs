@@ -0,0 +1,494 @@
+// Arithmetic for the inline calculator. A recursive-descent parser evaluates
+// as it goes; from lowest to highest precedence:
+//
+//   expr    = term (('+' | '-') term)*
+//   term    = unary (('*' | '/' | '%') unary)*
+//   unary   = ('-' | '+') unary | power
+//   power   = primary ('^' unary)?           right-associative, -2^2 = -4
+//   primary = number | constant | function '(' expr (',' expr)* ')' | '(' expr ')'
+//
+// Numbers may be decimal (with an exponent), 0x hex, 0o octal or 0b binary.
+
+// How deeply unary operators, parentheses and exponents may nest, so pasting a
+// long run of "(" or "-" is an error rather than a stack overflow.
+const MAX_NESTING: usize = 200;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    Comma,
+    Open,
+    Close,
+}
+
+// Evaluates `query` if it looks like arithmetic rather than an app name and
+// returns the formatted result. Plain numbers and bare constants like "e"
+// don't count; anything with an operator, a function call or a non-decimal
+// literal does.
+pub fn answer(query: &str) -> Option<String> {
+    let query = query.trim();
+    let has_operator = query.chars().any(|c| "+-*/%^()×÷".contains(c));
+    let lower = query.to_lowercase();
+    let has_literal = ["0x", "0o", "0b"]
+        .iter()
+        .any(|prefix| lower.contains(prefix));
+    if (!has_operator && !has_literal) || contains_date(query) {
+        return None;
+    }
+
+    let value = evaluate(query).ok()?;
+    let formatted = format_number(value);
+    (formatted != query).then_some(formatted)
+}
+
+// Dates like 2024-01-15 or 15-01-2024 are written with dashes, not subtracted,
+// wherever they are in the query.
+fn contains_date(query: &str) -> bool {
+    query
+        .split(|c: char| !(c.is_ascii_digit() || c == '-' || c == '.'))
+        .any(is_date)
+}
+
+fn is_date(word: &str) -> bool {
+    let parts: Vec<&str> = word.split('-').collect();
+    parts.len() == 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+pub fn evaluate(expr: &str) -> Result<f64, String> {
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Err("empty expression".to_string());
+    }
+
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.expr()?;
+    if let Some(token) = parser.peek() {
+        return Err(format!("unexpected {:?}", token));
+    }
+    if !value.is_finite() {
+        return Err("result is not a finite number".to_string());
+    }
+    Ok(value)
+}
+
+// Integers print exactly (up to what f64 holds exactly), everything else with
+// up to 10 decimals and trailing zeros trimmed; very large or small values in
+// scientific notation.
+pub fn format_number(value: f64) -> String {
+    if value == 0.0 {
+        return "0".to_string();
+    }
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        return format!("{}", value as i64);
+    }
+    if value.abs() >= 1e15 || value.abs() < 1e-6 {
+        return format!("{:e}", value);
+    }
+
+    let fixed = format!("{:.10}", value);
+    let trimmed = fixed.trim_end_matches('0').trim_end_matches('.');
+    if trimmed == "-0" {
+        "0".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' => i += 1,
+            '0'..='9' | '.' => {
+                let (value, len) = number(&chars[i..])?;
+                tokens.push(Token::Number(value));
+                i += len;
+            }
+            'a'..='z' | 'A'..='Z' | '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let ident: String = chars[start..i].iter().collect();
+                tokens.push(Token::Ident(ident.to_lowercase()));
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                tokens.push(Token::Op('^'));
+                i += 2;
+            }
+            '+' | '-' | '*' | '/' | '%' | '^' => {
+                tokens.push(Token::Op(c));
+                i += 1;
+            }
+            '×' => {
+                tokens.push(Token::Op('*'));
+                i += 1;
+            }
+            '÷' => {
+                tokens.push(Token::Op('/'));
+                i += 1;
+            }
+            '(' => {
+                tokens.push(Token::Open);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            _ => return Err(format!("unexpected character '{}'", c)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Parses the number at the start of `chars`, returning it and how many
+// characters it took.
+fn number(chars: &[char]) -> Result<(f64, usize), String> {
+    let radix = match (chars.first(), chars.get(1)) {
+        (Some('0'), Some('x' | 'X')) => Some(16),
+        (Some('0'), Some('o' | 'O')) => Some(8),
+        (Some('0'), Some('b' | 'B')) => Some(2),
+        _ => None,
+    };
+
+    if let Some(radix) = radix {
+        let digits: String = chars[2..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+            .collect();
+        let value = u64::from_str_radix(&digits.replace('_', ""), radix)
+            .map_err(|_| format!("invalid base-{} literal '0{}{}'", radix, chars[1], digits))?;
+        return Ok((value as f64, 2 + digits.chars().count()));
+    }
+
+    let mut len = 0;
+    while len < chars.len() && (chars[len].is_ascii_digit() || chars[len] == '.') {
+        len += 1;
+    }
+    // An exponent only counts when digits follow it.
+    if matches!(chars.get(len), Some('e' | 'E')) {
+        let mut end = len + 1;
+        if matches!(chars.get(end), Some('+' | '-')) {
+            end += 1;
+        }
+        if chars.get(end).is_some_and(|c| c.is_ascii_digit()) {
+            while end < chars.len() && chars[end].is_ascii_digit() {
+                end += 1;
+            }
+            len = end;
+        }
+    }
+
+    let text: String = chars[..len].iter().collect();
+    text.parse()
+        .map(|value| (value, len))
+        .map_err(|_| format!("invalid number '{}'", text))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // How many unary() calls are in progress; every kind of nesting goes
+    // through one
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat(&Token::Op('+')) {
+                value += self.term()?;
+            } else if self.eat(&Token::Op('-')) {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat(&Token::Op('*')) {
+                value *= self.unary()?;
+            } else if self.eat(&Token::Op('/')) {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err("division by zero".to_string());
+                }
+                value /= divisor;
+            } else if self.eat(&Token::Op('%')) {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err("division by zero".to_string());
+                }
+                value %= divisor;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<f64, String> {
+        if self.depth >= MAX_NESTING {
+            return Err("expression is nested too deeply".to_string());
+        }
+        self.depth += 1;
+        let value = self.signed();
+        self.depth -= 1;
+        value
+    }
+
+    fn signed(&mut self) -> Result<f64, String> {
+        if self.eat(&Token::Op('-')) {
+            return Ok(-self.unary()?);
+        }
+        if self.eat(&Token::Op('+')) {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<f64, String> {
+        let base = self.primary()?;
+        if self.eat(&Token::Op('^')) {
+            return Ok(base.powf(self.unary()?));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<f64, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Open) => {
+                let value = self.expr()?;
+                if !self.eat(&Token::Close) {
+                    return Err("missing ')'".to_string());
+                }
+                Ok(value)
+            }
+            Some(Token::Ident(name)) => {
+                if self.eat(&Token::Open) {
+                    let args = self.arguments()?;
+                    call(&name, &args)
+                } else {
+                    constant(&name)
+                }
+            }
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    // Comma-separated arguments up to and including the closing parenthesis.
+    fn arguments(&mut self) -> Result<Vec<f64>, String> {
+        let mut args = vec![self.expr()?];
+        while self.eat(&Token::Comma) {
+            args.push(self.expr()?);
+        }
+        if !self.eat(&Token::Close) {
+            return Err("missing ')'".to_string());
+        }
+        Ok(args)
+    }
+}
+
+fn constant(name: &str) -> Result<f64, String> {
+    match name {
+        "pi" => Ok(std::f64::consts::PI),
+        "tau" => Ok(std::f64::consts::TAU),
+        "e" => Ok(std::f64::consts::E),
+        _ => Err(format!("unknown constant '{}'", name)),
+    }
+}
+
+fn call(name: &str, args: &[f64]) -> Result<f64, String> {
+    let one = |f: fn(f64) -> f64| match args {
+        [x] => Ok(f(*x)),
+        _ => Err(format!("{}() takes 1 argument", name)),
+    };
+    let two = |f: fn(f64, f64) -> f64| match args {
+        [x, y] => Ok(f(*x, *y)),
+        _ => Err(format!("{}() takes 2 arguments", name)),
+    };
+
+    match name {
+        "sqrt" => one(f64::sqrt),
+        "cbrt" => one(f64::cbrt),
+        "abs" => one(f64::abs),
+        "exp" => one(f64::exp),
+        "ln" => one(f64::ln),
+        "log" | "log10" => one(f64::log10),
+        "log2" => one(f64::log2),
+        "sin" => one(f64::sin),
+        "cos" => one(f64::cos),
+        "tan" => one(f64::tan),
+        "asin" => one(f64::asin),
+        "acos" => one(f64::acos),
+        "atan" => one(f64::atan),
+        "floor" => one(f64::floor),
+        "ceil" => one(f64::ceil),
+        "round" => one(f64::round),
+        "pow" => two(f64::powf),
+        "atan2" => two(f64::atan2),
+        "min" | "max" if args.is_empty() => Err(format!("{}() needs arguments", name)),
+        "min" => Ok(args.iter().copied().fold(f64::INFINITY, f64::min)),
+        "max" => Ok(args.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+        _ => Err(format!("unknown function '{}'", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str) -> f64 {
+        evaluate(expr).unwrap()
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(eval("12*37/4"), 111.0);
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("10 - 4 - 3"), 3.0);
+        assert_eq!(eval("2^10"), 1024.0);
+        assert_eq!(eval("2**3^2"), 512.0);
+        assert_eq!(eval("-2^2"), -4.0);
+        assert_eq!(eval("2^-1"), 0.5);
+        assert_eq!(eval("17 % 5"), 2.0);
+        assert_eq!(eval("6 × 7 ÷ 2"), 21.0);
+    }
+
+    #[test]
+    fn functions_and_constants() {
+        assert!((eval("sqrt(2)") - std::f64::consts::SQRT_2).abs() < 1e-12);
+        assert_eq!(eval("max(3, 9, 4) - min(2, 5)"), 7.0);
+        assert_eq!(eval("pow(2, 8)"), 256.0);
+        assert_eq!(eval("log(1000)"), 3.0);
+        assert!((eval("2 * pi") - std::f64::consts::TAU).abs() < 1e-12);
+        assert!((eval("cos(0) + ln(e)") - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn number_literals() {
+        assert_eq!(eval("0xff"), 255.0);
+        assert_eq!(eval("0b1010 + 0o17"), 25.0);
+        assert_eq!(eval("0xFF_FF"), 65535.0);
+        assert_eq!(eval("1.5e3"), 1500.0);
+        assert_eq!(eval("2.5E-1"), 0.25);
+        assert_eq!(eval(".5 * 4"), 2.0);
+    }
+
+    #[test]
+    fn malformed_expressions_are_errors() {
+        for expr in [
+            "",
+            "1 +",
+            "(1 + 2",
+            "1 + 2)",
+            "2 ** ",
+            "sqrt()",
+            "sqrt(1, 2)",
+            "foo(1)",
+            "1 / 0",
+            "0xzz",
+            "1..2",
+            "firefox",
+            "3 $ 4",
+        ] {
+            assert!(evaluate(expr).is_err(), "{:?} should not evaluate", expr);
+        }
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format_number(111.0), "111");
+        assert_eq!(format_number(-4.0), "-4");
+        assert_eq!(format_number(0.1 + 0.2), "0.3");
+        assert_eq!(format_number(std::f64::consts::SQRT_2), "1.4142135624");
+        assert_eq!(format_number(1e20), "1e20");
+        assert_eq!(format_number(1.5e-9), "1.5e-9");
+    }
+
+    #[test]
+    fn only_arithmetic_queries_get_an_answer() {
+        assert_eq!(answer("12*37/4").as_deref(), Some("111"));
+        assert_eq!(answer("sqrt(2)").as_deref(), Some("1.4142135624"));
+        assert_eq!(answer("0xff").as_deref(), Some("255"));
+        assert_eq!(answer("firefox"), None);
+        assert_eq!(answer("e"), None);
+        assert_eq!(answer("2048"), None);
+        assert_eq!(answer("-5"), None);
+        assert_eq!(answer("gnome-terminal"), None);
+        assert_eq!(answer("10-3").as_deref(), Some("7"));
+    }
+
+    #[test]
+    fn dates_are_not_subtracted() {
+        assert_eq!(answer("2024-01-15"), None);
+        assert_eq!(answer("15-01-2024"), None);
+        assert_eq!(answer("2024-01-15 + 1"), None);
+        assert_eq!(answer("(2024-01-15)*2"), None);
+        assert_eq!(answer("2 * 2024-01-15"), None);
+
+        // Still subtracted when they can't be a date
+        assert_eq!(answer("2024-01").as_deref(), Some("2023"));
+        assert_eq!(answer("1.5-2-3").as_deref(), Some("-3.5"));
+        assert_eq!(answer("10 - 2 - 3").as_deref(), Some("5"));
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error_not_a_crash() {
+        let parens = format!("{}1{}", "(".repeat(50), ")".repeat(50));
+        assert_eq!(eval(&parens), 1.0);
+
+        for expr in [
+            format!("{}1", "(".repeat(100_000)),
+            format!("{}1", "-".repeat(100_000)),
+            format!("2{}", "^2".repeat(100_000)),
+            format!("{}1{}", "sqrt(".repeat(100_000), ")".repeat(100_000)),
+        ] {
+            assert!(evaluate(&expr).is_err());
+        }
+    }
+}
@@ -0,0 +1,450 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::history;
+
+// Searches starting with this list the clipboard history, e.g. "\"invoice".
+pub const PREFIXES: &[&str] = &["\""];
+
+// Unpinned entries beyond this many are dropped, oldest first. Pinned ones
+// are kept until they're unpinned or deleted.
+const MAX_ENTRIES: usize = 200;
+
+// Bigger selections, like a whole file copied as text, aren't recorded.
+const MAX_TEXT_BYTES: usize = 256 * 1024;
+const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+// How long a text preview gets before it's cut off.
+const PREVIEW_CHARS: usize = 80;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClipEntry {
+    pub id: u64,
+    pub content: ClipContent,
+    // When it was last copied, in seconds since the epoch
+    pub copied: i64,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ClipContent {
+    Text {
+        text: String,
+    },
+    // Stored next to the history as <id>.<extension>; the hash finds repeats
+    Image {
+        extension: String,
+        size: u64,
+        hash: u64,
+    },
+}
+
+// Copied text and images, most recent first, kept in
+// $XDG_STATE_HOME/bitpop/clipboard/ by `bitpop --clipboard-daemon` and read by
+// the popup. Both sides change it with `update`, which holds a lock on the
+// directory while it reloads, changes and saves the store, so edits from one
+// don't undo the other's.
+#[derive(Default, Serialize, Deserialize)]
+pub struct ClipboardStore {
+    #[serde(skip)]
+    dir: PathBuf,
+    next_id: u64,
+    entries: Vec<ClipEntry>,
+}
+
+impl ClipboardStore {
+    pub fn load() -> ClipboardStore {
+        ClipboardStore::load_from(&store_dir().unwrap_or_default())
+    }
+
+    pub fn load_from(dir: &Path) -> ClipboardStore {
+        let mut store: ClipboardStore = fs::read_to_string(dir.join("history.json"))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        store.dir = dir.to_path_buf();
+        store
+    }
+
+    fn save(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let content = serde_json::to_string(self).map_err(io::Error::other)?;
+
+        let path = self.dir.join("history.json");
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(tmp_path, path)
+    }
+
+    // Loads the store, applies `change` and saves it if anything changed.
+    pub fn update(change: impl FnOnce(&mut ClipboardStore) -> bool) -> io::Result<bool> {
+        let dir = store_dir().ok_or_else(|| io::Error::other("No state directory"))?;
+        ClipboardStore::update_in(&dir, change)
+    }
+
+    fn update_in(dir: &Path, change: impl FnOnce(&mut ClipboardStore) -> bool) -> io::Result<bool> {
+        let _lock = lock(dir)?;
+        let mut store = ClipboardStore::load_from(dir);
+        if !change(&mut store) {
+            return Ok(false);
+        }
+        store.save().map(|()| true)
+    }
+
+    // Adds a copied selection: text if it's UTF-8, an image if it looks like
+    // one, and nothing otherwise. Copying something already in the history
+    // moves it back to the top instead of adding it twice.
+    pub fn record(&mut self, data: &[u8], now: i64) -> io::Result<bool> {
+        let content = match (std::str::from_utf8(data), image_extension(data)) {
+            (Ok(text), _) if text.trim().is_empty() || data.len() > MAX_TEXT_BYTES => {
+                return Ok(false);
+            }
+            (Ok(text), _) => ClipContent::Text {
+                text: text.to_string(),
+            },
+            (Err(_), Some(extension)) if data.len() <= MAX_IMAGE_BYTES => ClipContent::Image {
+                extension: extension.to_string(),
+                size: data.len() as u64,
+                hash: fnv1a(data),
+            },
+            _ => return Ok(false),
+        };
+
+        let existing = self
+            .entries
+            .iter()
+            .position(|entry| entry.content == content);
+        let entry = match existing {
+            Some(position) => {
+                let mut entry = self.entries.remove(position);
+                entry.copied = now;
+                entry
+            }
+            None => {
+                self.next_id += 1;
+                let entry = ClipEntry {
+                    id: self.next_id,
+                    content,
+                    copied: now,
+                    pinned: false,
+                };
+                if let Some(path) = self.image_path(&entry) {
+                    fs::create_dir_all(&self.dir)?;
+                    fs::write(path, data)?;
+                }
+                entry
+            }
+        };
+        self.entries.insert(0, entry);
+        Ok(true)
+    }
+
+    // Drops unpinned entries older than `max_age` seconds, and the oldest
+    // unpinned ones beyond MAX_ENTRIES.
+    pub fn prune(&mut self, now: i64, max_age: Option<i64>) -> bool {
+        let mut unpinned = 0;
+        let (kept, dropped): (Vec<ClipEntry>, Vec<ClipEntry>) =
+            self.entries.drain(..).partition(|entry| {
+                if entry.pinned {
+                    return true;
+                }
+                unpinned += 1;
+                let expired = max_age.is_some_and(|max_age| now - entry.copied > max_age);
+                !expired && unpinned <= MAX_ENTRIES
+            });
+        self.entries = kept;
+
+        for entry in &dropped {
+            self.remove_image(entry);
+        }
+        !dropped.is_empty()
+    }
+
+    pub fn toggle_pin(&mut self, id: u64) -> bool {
+        let Some(entry) = self.entries.iter_mut().find(|entry| entry.id == id) else {
+            return false;
+        };
+        entry.pinned = !entry.pinned;
+        true
+    }
+
+    pub fn delete(&mut self, id: u64) -> bool {
+        let Some(position) = self.entries.iter().position(|entry| entry.id == id) else {
+            return false;
+        };
+        let entry = self.entries.remove(position);
+        self.remove_image(&entry);
+        true
+    }
+
+    // Entries whose text contains `query` (ignoring case), or all of them for
+    // an empty one; images match the word "image". Pinned entries come first,
+    // then the most recently copied.
+    pub fn search(&self, query: &str) -> Vec<&ClipEntry> {
+        let query = query.to_lowercase();
+        let mut matches: Vec<&ClipEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| match &entry.content {
+                ClipContent::Text { text } => text.to_lowercase().contains(&query),
+                ClipContent::Image { .. } => "image".contains(&query),
+            })
+            .collect();
+        matches.sort_by_key(|entry| !entry.pinned);
+        matches
+    }
+
+    pub fn image_path(&self, entry: &ClipEntry) -> Option<PathBuf> {
+        match &entry.content {
+            ClipContent::Image { extension, .. } => {
+                Some(self.dir.join(format!("{}.{}", entry.id, extension)))
+            }
+            ClipContent::Text { .. } => None,
+        }
+    }
+
+    fn remove_image(&self, entry: &ClipEntry) {
+        if let Some(path) = self.image_path(entry) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// Waits for and takes the store's lock, which is released when the returned
+// file is closed.
+fn lock(dir: &Path) -> io::Result<File> {
+    fs::create_dir_all(dir)?;
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join("lock"))?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+// Records a selection handed over by `wl-paste --watch`, which says in
+// CLIPBOARD_STATE whether there is one and whether a password manager marked
+// it sensitive; only plain data is kept.
+pub fn record_selection(selection: impl Read, state: Option<&str>) -> io::Result<()> {
+    if state.is_some_and(|state| state != "data") {
+        return Ok(());
+    }
+    // Anything longer than the limit is too big to record either way
+    let mut data = Vec::new();
+    selection
+        .take(MAX_IMAGE_BYTES as u64 + 1)
+        .read_to_end(&mut data)?;
+
+    let now = history::now();
+    let max_age = Config::load().clipboard_max_age;
+    ClipboardStore::update(|store| {
+        let recorded = store.record(&data, now).unwrap_or(false);
+        store.prune(now, max_age) || recorded
+    })
+    .map(|_| ())
+}
+
+// The first non-blank line of `text`, trimmed and cut to PREVIEW_CHARS.
+pub fn preview(text: &str) -> String {
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    if line.chars().count() <= PREVIEW_CHARS {
+        return line.to_string();
+    }
+    let mut preview: String = line.chars().take(PREVIEW_CHARS - 1).collect();
+    preview.push('…');
+    preview
+}
+
+// How long ago `time` was, roughly: "just now", "5 min ago", "2 days ago".
+pub fn ago(time: i64, now: i64) -> String {
+    let seconds = (now - time).max(0);
+    match seconds {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", seconds / 60),
+        3600..86400 => format!("{} h ago", seconds / 3600),
+        86400..172800 => "yesterday".to_string(),
+        _ => format!("{} days ago", seconds / 86400),
+    }
+}
+
+// The file extension for image data, from its magic bytes.
+fn image_extension(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        Some("jpg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("gif")
+    } else if data.len() > 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("webp")
+    } else {
+        None
+    }
+}
+
+// FNV-1a, which unlike std's hasher gives the same hash in every build, as
+// hashes stored on disk have to.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn store_dir() -> Option<PathBuf> {
+    dirs::state_dir().map(|dir| dir.join("bitpop").join("clipboard"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR\xff";
+
+    fn temp_store(name: &str) -> ClipboardStore {
+        let dir = env::temp_dir().join(format!("bitpop-clipboard-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        ClipboardStore::load_from(&dir)
+    }
+
+    fn texts(entries: &[&ClipEntry]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|entry| match &entry.content {
+                ClipContent::Text { text } => text.clone(),
+                ClipContent::Image { .. } => "<image>".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn selections_are_recorded_most_recent_first_without_repeats() {
+        let mut store = temp_store("record");
+        assert!(store.record(b"first", 1).unwrap());
+        assert!(store.record(b"second", 2).unwrap());
+        assert!(store.record(PNG, 3).unwrap());
+        assert!(store.record(b"first", 4).unwrap());
+        assert!(!store.record(b"  \n", 5).unwrap());
+        assert!(!store.record(b"\x00\xff binary", 6).unwrap());
+
+        assert_eq!(texts(&store.search("")), vec!["first", "<image>", "second"]);
+        assert_eq!(store.entries[0].copied, 4);
+        let image = &store.entries[1];
+        assert_eq!(fs::read(store.image_path(image).unwrap()).unwrap(), PNG);
+
+        store.save().unwrap();
+        let reloaded = ClipboardStore::load_from(&store.dir);
+        assert_eq!(reloaded.entries, store.entries);
+
+        // A new entry after reloading doesn't reuse an ID
+        let mut reloaded = reloaded;
+        reloaded.record(b"third", 7).unwrap();
+        assert_eq!(reloaded.entries[0].id, 4);
+
+        fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[test]
+    fn pinned_entries_survive_pruning_and_come_first() {
+        let mut store = temp_store("prune");
+        store.record(b"old", 0).unwrap();
+        store.record(b"pinned", 10).unwrap();
+        store.record(PNG, 20).unwrap();
+        store.record(b"new", 1000).unwrap();
+        let pinned_id = store.entries[2].id;
+        let image_path = store.image_path(&store.entries[1]).unwrap();
+        assert!(store.toggle_pin(pinned_id));
+
+        assert!(store.prune(1000, Some(100)));
+        assert_eq!(texts(&store.search("")), vec!["pinned", "new"]);
+        assert!(!image_path.exists());
+        assert!(!store.prune(1000, Some(100)));
+
+        for i in 0..MAX_ENTRIES + 5 {
+            store
+                .record(format!("entry {}", i).as_bytes(), 2000)
+                .unwrap();
+        }
+        store.prune(2000, None);
+        assert_eq!(store.entries.len(), MAX_ENTRIES + 1);
+        assert!(store.entries.iter().any(|entry| entry.pinned));
+
+        fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[test]
+    fn entries_are_searched_and_deleted() {
+        let mut store = temp_store("search");
+        store.record(b"Invoice 2024-117", 1).unwrap();
+        store.record(b"https://example.com", 2).unwrap();
+        store.record(PNG, 3).unwrap();
+
+        assert_eq!(texts(&store.search("INVOICE")), vec!["Invoice 2024-117"]);
+        assert_eq!(texts(&store.search("img")), Vec::<String>::new());
+        assert_eq!(texts(&store.search("image")), vec!["<image>"]);
+
+        let image = store.entries[0].clone();
+        assert!(store.delete(image.id));
+        assert!(!store.delete(image.id));
+        assert!(!store.image_path(&image).unwrap().exists());
+        assert_eq!(store.entries.len(), 2);
+
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn concurrent_updates_keep_every_entry() {
+        let dir = temp_store("concurrent").dir;
+        let writers: Vec<_> = (0..4)
+            .map(|writer| {
+                let dir = dir.clone();
+                std::thread::spawn(move || {
+                    for n in 0..10 {
+                        let text = format!("{}-{}", writer, n);
+                        ClipboardStore::update_in(&dir, |store| {
+                            store.record(text.as_bytes(), n).unwrap()
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let store = ClipboardStore::load_from(&dir);
+        let mut ids: Vec<u64> = store.entries.iter().map(|entry| entry.id).collect();
+        ids.sort();
+        assert_eq!(ids, (1..=40).collect::<Vec<u64>>());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn previews_and_ages_are_short() {
+        assert_eq!(preview("\n  first line  \nsecond"), "first line");
+        let long = "x".repeat(200);
+        assert_eq!(preview(&long).chars().count(), PREVIEW_CHARS);
+        assert!(preview(&long).ends_with('…'));
+
+        assert_eq!(ago(100, 130), "just now");
+        assert_eq!(ago(0, 300), "5 min ago");
+        assert_eq!(ago(0, 7200), "2 h ago");
+        assert_eq!(ago(0, 100_000), "yesterday");
+        assert_eq!(ago(0, 3 * 86400), "3 days ago");
+    }
+}
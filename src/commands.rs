@@ -0,0 +1,326 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::apps::{is_executable, spawn_detached};
+use crate::terminal::wrap_in_terminal;
+
+// Starting a search with one of these turns the rest of it into a shell
+// command line, e.g. "> make -C ~/src/bitpop".
+pub const PREFIXES: &[&str] = &[">", "!"];
+
+// Commands beyond this many are dropped from the history, oldest first.
+const MAX_HISTORY: usize = 200;
+
+// Executable names only complete the first word, and a one-letter prefix can
+// match hundreds of them.
+const MAX_COMPLETIONS: usize = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompletionKind {
+    // The command line exactly as typed
+    Typed,
+    // A command run before that starts with what was typed
+    History,
+    // A program in $PATH whose name starts with the first word
+    Executable,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Completion {
+    pub line: String,
+    pub kind: CompletionKind,
+}
+
+// The command line of a prefixed search, or None for ordinary searches.
+pub fn command_line(text: &str) -> Option<&str> {
+    let text = text.trim_start();
+    PREFIXES
+        .iter()
+        .find_map(|prefix| text.strip_prefix(prefix))
+        .map(|line| line.trim_start())
+}
+
+// The entry text after completing `text` to `completion`: the prefix is kept
+// as typed and a space follows, ready for arguments.
+pub fn complete(text: &str, completion: &str) -> Option<String> {
+    let line = command_line(text)?;
+    let prefix = &text[..text.len() - line.len()];
+    Some(format!("{}{} ", prefix, completion))
+}
+
+// What the results list shows for a command line: the line itself, then
+// earlier commands it is the start of (most recent first), then programs it
+// could be the name of.
+pub fn completions(
+    line: &str,
+    history: &CommandHistory,
+    executables: &[String],
+) -> Vec<Completion> {
+    let mut seen = HashSet::new();
+    let mut completions = Vec::new();
+    let mut push = |line: &str, kind| {
+        if seen.insert(line.to_string()) {
+            completions.push(Completion {
+                line: line.to_string(),
+                kind,
+            });
+        }
+    };
+
+    let typed = line.trim();
+    if !typed.is_empty() {
+        push(typed, CompletionKind::Typed);
+    }
+
+    for command in history
+        .commands
+        .iter()
+        .filter(|command| command.starts_with(typed))
+    {
+        push(command, CompletionKind::History);
+    }
+
+    if !typed.is_empty() && !line.contains(char::is_whitespace) {
+        for name in executables
+            .iter()
+            .filter(|name| name.starts_with(typed))
+            .take(MAX_COMPLETIONS)
+        {
+            push(name, CompletionKind::Executable);
+        }
+    }
+
+    completions
+}
+
+// The names of all executables in `path`, sorted and without duplicates.
+pub fn path_executables(path: &[PathBuf]) -> Vec<String> {
+    let mut names: Vec<String> = path
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .filter(|entry| is_executable(&entry.path()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+// Runs `line` with sh from the home directory, detached like launched apps.
+// In a terminal an interactive shell takes over once the command is done, so
+// its output stays readable.
+pub fn run(line: &str, in_terminal: bool) -> Result<(), String> {
+    let mut argv = shell_argv(line, in_terminal);
+    if in_terminal {
+        argv = wrap_in_terminal(&argv).map_err(|err| format!("Couldn't run {}: {}", line, err))?;
+    }
+
+    spawn_detached(&argv, dirs::home_dir().as_deref())
+        .map_err(|err| format!("Couldn't run {}: {}", line, err))?;
+
+    record_command(line);
+    Ok(())
+}
+
+fn shell_argv(line: &str, in_terminal: bool) -> Vec<String> {
+    let script = if in_terminal {
+        "eval \"$1\"; exec \"${SHELL:-sh}\""
+    } else {
+        "eval \"$1\""
+    };
+    ["sh", "-c", script, "sh", line]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect()
+}
+
+// Commands run from the search entry, most recent first, stored one per line
+// in $XDG_STATE_HOME/bitpop/commands.
+#[derive(Default)]
+pub struct CommandHistory {
+    commands: Vec<String>,
+}
+
+impl CommandHistory {
+    pub fn load() -> CommandHistory {
+        commands_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|content| CommandHistory::parse(&content))
+            .unwrap_or_default()
+    }
+
+    fn parse(content: &str) -> CommandHistory {
+        let mut history = CommandHistory::default();
+        for line in content.lines().rev() {
+            history.record(line);
+        }
+        history
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = commands_path().ok_or(io::ErrorKind::NotFound)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, self.to_text())?;
+        fs::rename(tmp_path, path)
+    }
+
+    fn to_text(&self) -> String {
+        self.commands
+            .iter()
+            .map(|command| format!("{}\n", command))
+            .collect()
+    }
+
+    // Moves `line` to the front. Multi-line commands aren't kept, since the
+    // file has one command per line.
+    pub fn record(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() || line.contains('\n') {
+            return;
+        }
+        self.forget(line);
+        self.commands.insert(0, line.to_string());
+        self.commands.truncate(MAX_HISTORY);
+    }
+
+    pub fn forget(&mut self, line: &str) -> bool {
+        let count = self.commands.len();
+        self.commands.retain(|command| command != line);
+        self.commands.len() != count
+    }
+}
+
+fn record_command(line: &str) {
+    let mut history = CommandHistory::load();
+    history.record(line);
+    let _ = history.save();
+}
+
+fn commands_path() -> Option<PathBuf> {
+    dirs::state_dir().map(|dir| dir.join("bitpop").join("commands"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn lines(completions: &[Completion]) -> Vec<(&str, CompletionKind)> {
+        completions
+            .iter()
+            .map(|completion| (completion.line.as_str(), completion.kind))
+            .collect()
+    }
+
+    #[test]
+    fn prefixes_start_a_command_line() {
+        assert_eq!(command_line("> make -j8"), Some("make -j8"));
+        assert_eq!(command_line("!htop"), Some("htop"));
+        assert_eq!(command_line("  >"), Some(""));
+        assert_eq!(command_line("firefox"), None);
+        assert_eq!(command_line("2>1"), None);
+
+        assert_eq!(complete("> fi", "firefox").as_deref(), Some("> firefox "));
+        assert_eq!(complete("!fi", "firefox").as_deref(), Some("!firefox "));
+        assert_eq!(complete("fi", "firefox"), None);
+    }
+
+    #[test]
+    fn completions_list_typed_line_history_then_executables() {
+        let history = CommandHistory::parse("git status\ngit log\nls -la\n");
+        let executables = vec!["gimp".to_string(), "git".to_string(), "gzip".to_string()];
+
+        assert_eq!(
+            lines(&completions("gi", &history, &executables)),
+            vec![
+                ("gi", CompletionKind::Typed),
+                ("git status", CompletionKind::History),
+                ("git log", CompletionKind::History),
+                ("gimp", CompletionKind::Executable),
+                ("git", CompletionKind::Executable),
+            ]
+        );
+
+        // Past the program name only the history can complete
+        assert_eq!(
+            lines(&completions("git l", &history, &executables)),
+            vec![
+                ("git l", CompletionKind::Typed),
+                ("git log", CompletionKind::History),
+            ]
+        );
+
+        // Nothing typed yet: recent commands only
+        assert_eq!(completions("", &history, &executables).len(), 3);
+
+        // The typed line isn't repeated when it was run before
+        assert_eq!(
+            lines(&completions("ls -la", &history, &executables)),
+            vec![("ls -la", CompletionKind::Typed)]
+        );
+    }
+
+    #[test]
+    fn history_moves_repeated_commands_to_the_front_and_is_bounded() {
+        let mut history = CommandHistory::parse("b\na\n");
+        history.record("a");
+        history.record("  c  ");
+        history.record("two\nlines");
+        history.record("");
+        assert_eq!(history.to_text(), "c\na\nb\n");
+
+        assert!(history.forget("a"));
+        assert!(!history.forget("a"));
+        assert_eq!(history.to_text(), "c\nb\n");
+
+        for i in 0..MAX_HISTORY + 10 {
+            history.record(&i.to_string());
+        }
+        assert_eq!(history.commands.len(), MAX_HISTORY);
+        assert_eq!(history.commands[0], (MAX_HISTORY + 9).to_string());
+    }
+
+    #[test]
+    fn executables_come_from_every_path_directory() {
+        let root = env::temp_dir().join(format!("bitpop-path-{}", std::process::id()));
+        let (first, second) = (root.join("first"), root.join("second"));
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+
+        let write = |path: PathBuf, mode: u32| {
+            fs::write(&path, "#!/bin/sh\n").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        };
+        write(first.join("zap"), 0o755);
+        write(first.join("notes.txt"), 0o644);
+        write(second.join("zap"), 0o755);
+        write(second.join("bar"), 0o700);
+        fs::create_dir_all(second.join("subdir")).unwrap();
+
+        let path = vec![first, second, root.join("missing")];
+        assert_eq!(path_executables(&path), vec!["bar", "zap"]);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn terminal_commands_leave_a_shell_open() {
+        assert_eq!(
+            shell_argv("ls ~", false),
+            vec!["sh", "-c", "eval \"$1\"", "sh", "ls ~"]
+        );
+        assert_eq!(
+            shell_argv("ls ~", true)[2],
+            "eval \"$1\"; exec \"${SHELL:-sh}\""
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::desktop_entry::{KeyFile, join_list, set_value};
+use crate::plugins::PluginConfig;
+
+// User settings from ~/.config/bitpop/bitpop.conf, written in the same
+// key-file format as .desktop files:
+//
+//   [General]
+//   Terminal=foot
+//
+//   [Pinned]
+//   Apps=firefox.desktop;org.gnome.Nautilus.desktop;
+//
+//   [Files]
+//   Roots=~;~/projects;
+//
+//   [Clipboard]
+//   MaxAge=30
+//
+//   [Plugin tickets]
+//   Exec=ticket-search
+//   Prefix=#
+#[derive(Default)]
+pub struct Config {
+    pub terminal: Option<String>,
+    pub pinned: Vec<String>,
+    pub file_roots: Vec<PathBuf>,
+    pub plugins: Vec<PluginConfig>,
+    // How long unpinned clipboard entries are kept, in seconds (MaxAge is in
+    // days); None keeps them until the history is full.
+    pub clipboard_max_age: Option<i64>,
+}
+
+impl Config {
+    pub fn load() -> Config {
+        let Some(content) = config_path().and_then(|path| fs::read_to_string(path).ok()) else {
+            return Config::default();
+        };
+        let file = KeyFile::parse(&content);
+
+        let general = file.group("General");
+        let pinned = file.group("Pinned");
+        let files = file.group("Files");
+        let clipboard = file.group("Clipboard");
+
+        Config {
+            terminal: general
+                .and_then(|g| g.string("Terminal"))
+                .filter(|s| !s.is_empty()),
+            pinned: pinned.map(|g| g.string_list("Apps")).unwrap_or_default(),
+            file_roots: files
+                .map(|g| g.string_list("Roots"))
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|root| expand_home(root))
+                .collect(),
+            clipboard_max_age: clipboard
+                .and_then(|g| g.raw("MaxAge"))
+                .and_then(|days| days.parse::<i64>().ok())
+                .filter(|days| *days > 0)
+                .map(|days| days * 24 * 60 * 60),
+            plugins: file.groups().filter_map(PluginConfig::from_group).collect(),
+        }
+    }
+}
+
+// Stores the pinned desktop file IDs, in order, without disturbing anything
+// else the user wrote in the file.
+pub fn save_pinned(ids: &[String]) -> io::Result<()> {
+    save_pinned_to(&config_path().ok_or(io::ErrorKind::NotFound)?, ids)
+}
+
+// A file that can't be read is left alone rather than replaced by one with
+// only the pins in it.
+fn save_pinned_to(path: &Path, ids: &[String]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+
+    // Write-then-rename so a crash mid-write can't truncate the config.
+    let tmp_path = path.with_extension("tmp");
+    fs::write(
+        &tmp_path,
+        set_value(&content, "Pinned", "Apps", &join_list(ids)),
+    )?;
+    fs::rename(tmp_path, path)
+}
+
+// "~" and "~/..." are relative to the home directory; other relative paths
+// are ignored since there is no sensible directory to resolve them against.
+pub fn expand_home(path: &str) -> Option<PathBuf> {
+    let path = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            dirs::home_dir()?.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    };
+    path.is_absolute().then_some(path)
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("bitpop").join("bitpop.conf"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn temp_config(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("bitpop-config-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("bitpop.conf")
+    }
+
+    #[test]
+    fn pins_are_saved_next_to_the_other_settings() {
+        let path = temp_config("pins");
+        save_pinned_to(&path, &["firefox.desktop".to_string()]).unwrap();
+        fs::write(
+            &path,
+            format!(
+                "[General]\nTerminal=foot\n\n{}",
+                fs::read_to_string(&path).unwrap()
+            ),
+        )
+        .unwrap();
+
+        save_pinned_to(
+            &path,
+            &["foot.desktop".to_string(), "gimp.desktop".to_string()],
+        )
+        .unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("Terminal=foot"));
+        assert!(content.contains("Apps=foot.desktop;gimp.desktop;"));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn an_unreadable_config_is_not_overwritten() {
+        let path = temp_config("unreadable");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, b"[General]\nTerminal=\xff\n").unwrap();
+
+        assert!(save_pinned_to(&path, &["foot.desktop".to_string()]).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"[General]\nTerminal=\xff\n");
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,384 @@
+use chrono::{DateTime, Local, TimeZone};
+
+use crate::calc::format_number;
+
+// Offline unit conversions for queries like "10 km in mi", "72 F to C" or
+// "5 GiB in MB", plus "1700000000 to date" for Unix timestamps.
+
+#[derive(Clone, Copy, PartialEq)]
+enum Dimension {
+    Length,
+    Mass,
+    Time,
+    Data,
+    Volume,
+    Area,
+    Speed,
+    Temperature,
+}
+
+// A value in this unit converts to the dimension's base unit as
+// (value + offset) * factor. Only temperatures need the offset.
+struct Unit {
+    names: &'static [&'static str],
+    dimension: Dimension,
+    factor: f64,
+    offset: f64,
+}
+
+const fn unit(names: &'static [&'static str], dimension: Dimension, factor: f64) -> Unit {
+    Unit {
+        names,
+        dimension,
+        factor,
+        offset: 0.0,
+    }
+}
+
+// The first name of each unit is the symbol results are shown with.
+const UNITS: &[Unit] = &[
+    // Length, in metres
+    unit(
+        &["m", "meter", "meters", "metre", "metres"],
+        Dimension::Length,
+        1.0,
+    ),
+    unit(
+        &["km", "kilometer", "kilometers", "kilometre", "kilometres"],
+        Dimension::Length,
+        1e3,
+    ),
+    unit(
+        &[
+            "cm",
+            "centimeter",
+            "centimeters",
+            "centimetre",
+            "centimetres",
+        ],
+        Dimension::Length,
+        1e-2,
+    ),
+    unit(
+        &[
+            "mm",
+            "millimeter",
+            "millimeters",
+            "millimetre",
+            "millimetres",
+        ],
+        Dimension::Length,
+        1e-3,
+    ),
+    unit(
+        &["µm", "um", "micrometer", "micrometers"],
+        Dimension::Length,
+        1e-6,
+    ),
+    unit(&["nm", "nanometer", "nanometers"], Dimension::Length, 1e-9),
+    unit(&["mi", "mile", "miles"], Dimension::Length, 1609.344),
+    unit(&["yd", "yard", "yards"], Dimension::Length, 0.9144),
+    unit(&["ft", "foot", "feet"], Dimension::Length, 0.3048),
+    unit(&["in", "inch", "inches"], Dimension::Length, 0.0254),
+    unit(
+        &["nmi", "nautical-mile", "nautical-miles"],
+        Dimension::Length,
+        1852.0,
+    ),
+    // Mass, in kilograms
+    unit(
+        &["kg", "kilogram", "kilograms", "kilo", "kilos"],
+        Dimension::Mass,
+        1.0,
+    ),
+    unit(&["g", "gram", "grams"], Dimension::Mass, 1e-3),
+    unit(&["mg", "milligram", "milligrams"], Dimension::Mass, 1e-6),
+    unit(&["t", "tonne", "tonnes"], Dimension::Mass, 1e3),
+    unit(
+        &["lb", "lbs", "pound", "pounds"],
+        Dimension::Mass,
+        0.45359237,
+    ),
+    unit(&["oz", "ounce", "ounces"], Dimension::Mass, 0.028349523125),
+    unit(&["st", "stone", "stones"], Dimension::Mass, 6.35029318),
+    // Time, in seconds
+    unit(
+        &["s", "sec", "secs", "second", "seconds"],
+        Dimension::Time,
+        1.0,
+    ),
+    unit(
+        &["ms", "millisecond", "milliseconds"],
+        Dimension::Time,
+        1e-3,
+    ),
+    unit(&["min", "mins", "minute", "minutes"], Dimension::Time, 60.0),
+    unit(
+        &["h", "hr", "hrs", "hour", "hours"],
+        Dimension::Time,
+        3600.0,
+    ),
+    unit(&["d", "day", "days"], Dimension::Time, 86400.0),
+    unit(&["wk", "week", "weeks"], Dimension::Time, 604800.0),
+    unit(&["yr", "year", "years"], Dimension::Time, 31557600.0),
+    // Data, in bytes. Decimal prefixes are powers of 1000, binary ones of 1024.
+    unit(&["B", "byte", "bytes"], Dimension::Data, 1.0),
+    unit(&["kB", "KB", "kilobyte", "kilobytes"], Dimension::Data, 1e3),
+    unit(&["MB", "megabyte", "megabytes"], Dimension::Data, 1e6),
+    unit(&["GB", "gigabyte", "gigabytes"], Dimension::Data, 1e9),
+    unit(&["TB", "terabyte", "terabytes"], Dimension::Data, 1e12),
+    unit(&["PB", "petabyte", "petabytes"], Dimension::Data, 1e15),
+    unit(&["KiB", "kibibyte", "kibibytes"], Dimension::Data, 1024.0),
+    unit(
+        &["MiB", "mebibyte", "mebibytes"],
+        Dimension::Data,
+        1048576.0,
+    ),
+    unit(
+        &["GiB", "gibibyte", "gibibytes"],
+        Dimension::Data,
+        1073741824.0,
+    ),
+    unit(
+        &["TiB", "tebibyte", "tebibytes"],
+        Dimension::Data,
+        1099511627776.0,
+    ),
+    unit(
+        &["PiB", "pebibyte", "pebibytes"],
+        Dimension::Data,
+        1125899906842624.0,
+    ),
+    unit(&["bit", "bits", "b"], Dimension::Data, 0.125),
+    unit(
+        &["kbit", "kb", "kilobit", "kilobits"],
+        Dimension::Data,
+        125.0,
+    ),
+    unit(
+        &["Mbit", "Mb", "megabit", "megabits"],
+        Dimension::Data,
+        125e3,
+    ),
+    unit(
+        &["Gbit", "Gb", "gigabit", "gigabits"],
+        Dimension::Data,
+        125e6,
+    ),
+    // Volume, in litres
+    unit(
+        &["l", "L", "liter", "liters", "litre", "litres"],
+        Dimension::Volume,
+        1.0,
+    ),
+    unit(
+        &[
+            "ml",
+            "mL",
+            "milliliter",
+            "milliliters",
+            "millilitre",
+            "millilitres",
+        ],
+        Dimension::Volume,
+        1e-3,
+    ),
+    unit(
+        &["m3", "m³", "cubic-meter", "cubic-meters"],
+        Dimension::Volume,
+        1e3,
+    ),
+    unit(
+        &["gal", "gallon", "gallons"],
+        Dimension::Volume,
+        3.785411784,
+    ),
+    unit(&["qt", "quart", "quarts"], Dimension::Volume, 0.946352946),
+    unit(&["pt", "pint", "pints"], Dimension::Volume, 0.473176473),
+    unit(&["cup", "cups"], Dimension::Volume, 0.2365882365),
+    unit(&["floz", "fl-oz"], Dimension::Volume, 0.0295735295625),
+    // Area, in square metres
+    unit(&["m2", "m²", "sqm"], Dimension::Area, 1.0),
+    unit(&["km2", "km²"], Dimension::Area, 1e6),
+    unit(&["ft2", "ft²", "sqft"], Dimension::Area, 0.09290304),
+    unit(&["ha", "hectare", "hectares"], Dimension::Area, 1e4),
+    unit(&["acre", "acres"], Dimension::Area, 4046.8564224),
+    // Speed, in metres per second
+    unit(&["m/s", "mps"], Dimension::Speed, 1.0),
+    unit(&["km/h", "kmh", "kph"], Dimension::Speed, 1.0 / 3.6),
+    unit(&["mph"], Dimension::Speed, 0.44704),
+    unit(&["kn", "kt", "knot", "knots"], Dimension::Speed, 0.514444),
+    // Temperature, in kelvin
+    Unit {
+        names: &["°C", "C", "celsius"],
+        dimension: Dimension::Temperature,
+        factor: 1.0,
+        offset: 273.15,
+    },
+    Unit {
+        names: &["°F", "F", "fahrenheit"],
+        dimension: Dimension::Temperature,
+        factor: 5.0 / 9.0,
+        offset: 459.67,
+    },
+    Unit {
+        names: &["K", "kelvin"],
+        dimension: Dimension::Temperature,
+        factor: 1.0,
+        offset: 0.0,
+    },
+];
+
+const SEPARATORS: &[&str] = &["in", "to", "as", "->"];
+
+// The converted value for queries of the form "<amount> <unit> in <unit>"
+// (or "to", "as", "->"), or None when the query isn't one.
+pub fn answer(query: &str) -> Option<String> {
+    convert(query, &Local)
+}
+
+fn convert<Tz: TimeZone>(query: &str, timezone: &Tz) -> Option<String>
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let words: Vec<&str> = query.split_whitespace().collect();
+
+    // Units can be separators too ("5 in in cm"), so every separator position
+    // is tried until both sides make sense.
+    for (i, word) in words.iter().enumerate() {
+        if !SEPARATORS.contains(&word.to_lowercase().as_str()) || i == 0 {
+            continue;
+        }
+        let from = words[..i].join(" ");
+        let to = words[i + 1..].join(" ");
+
+        if to.eq_ignore_ascii_case("date") {
+            if let Some(date) = timestamp_to_date(&from, timezone) {
+                return Some(date);
+            }
+            continue;
+        }
+
+        let Some((amount, from_unit)) = split_amount(&from) else {
+            continue;
+        };
+        let (Some(from_unit), Some(to_unit)) = (find_unit(from_unit), find_unit(&to)) else {
+            continue;
+        };
+        if from_unit.dimension != to_unit.dimension {
+            continue;
+        }
+
+        let base = (amount + from_unit.offset) * from_unit.factor;
+        let value = base / to_unit.factor - to_unit.offset;
+        return Some(format!("{} {}", format_number(value), to_unit.names[0]));
+    }
+
+    None
+}
+
+// Splits "10km" or "10 km" into the amount and the unit name.
+fn split_amount(text: &str) -> Option<(f64, &str)> {
+    let unit_start = text
+        .char_indices()
+        .find(|(i, c)| !(c.is_ascii_digit() || *c == '.' || (*i == 0 && (*c == '-' || *c == '+'))))
+        .map(|(i, _)| i)?;
+    let amount = text[..unit_start].parse().ok()?;
+    let unit = text[unit_start..].trim();
+    (!unit.is_empty()).then_some((amount, unit))
+}
+
+// Exact matches win, so "Mb" (megabit) and "MB" (megabyte) stay apart;
+// otherwise a case-insensitive match is accepted if it is unambiguous.
+fn find_unit(name: &str) -> Option<&'static Unit> {
+    if let Some(unit) = UNITS.iter().find(|unit| unit.names.contains(&name)) {
+        return Some(unit);
+    }
+
+    let mut matches = UNITS.iter().filter(|unit| {
+        unit.names
+            .iter()
+            .any(|unit_name| unit_name.eq_ignore_ascii_case(name))
+    });
+    let unit = matches.next()?;
+    matches.next().is_none().then_some(unit)
+}
+
+// Seconds since the epoch, or milliseconds for values too large to be seconds
+// in any plausible year.
+fn timestamp_to_date<Tz: TimeZone>(text: &str, timezone: &Tz) -> Option<String>
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let timestamp: i64 = text.parse().ok()?;
+    let date: DateTime<Tz> = if timestamp.abs() >= 100_000_000_000 {
+        timezone.timestamp_millis_opt(timestamp).single()?
+    } else {
+        timezone.timestamp_opt(timestamp, 0).single()?
+    };
+    Some(date.format("%Y-%m-%d %H:%M:%S %:z").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    fn convert(query: &str) -> Option<String> {
+        super::convert(query, &Utc)
+    }
+
+    #[test]
+    fn length_mass_and_volume() {
+        assert_eq!(convert("10 km in mi").as_deref(), Some("6.2137119224 mi"));
+        assert_eq!(convert("10km to miles").as_deref(), Some("6.2137119224 mi"));
+        assert_eq!(convert("6 ft in cm").as_deref(), Some("182.88 cm"));
+        assert_eq!(convert("1 lb to g").as_deref(), Some("453.59237 g"));
+        assert_eq!(convert("2 gal in l").as_deref(), Some("7.570823568 l"));
+    }
+
+    #[test]
+    fn temperatures_use_offsets() {
+        assert_eq!(convert("72 F to C").as_deref(), Some("22.2222222222 °C"));
+        assert_eq!(convert("-40 °C in °F").as_deref(), Some("-40 °F"));
+        assert_eq!(convert("0 K to celsius").as_deref(), Some("-273.15 °C"));
+    }
+
+    #[test]
+    fn data_sizes_distinguish_binary_and_decimal_prefixes() {
+        assert_eq!(convert("5 GiB in MB").as_deref(), Some("5368.70912 MB"));
+        assert_eq!(
+            convert("1 GB in MiB").as_deref(),
+            Some("953.6743164062 MiB")
+        );
+        assert_eq!(convert("100 Mbit in MB").as_deref(), Some("12.5 MB"));
+        assert_eq!(convert("1 MB in Mb").as_deref(), Some("8 Mbit"));
+    }
+
+    #[test]
+    fn units_that_double_as_separators() {
+        assert_eq!(convert("12 in in cm").as_deref(), Some("30.48 cm"));
+        assert_eq!(convert("30 cm to in").as_deref(), Some("11.811023622 in"));
+    }
+
+    #[test]
+    fn timestamps_to_dates() {
+        assert_eq!(
+            convert("1700000000 to date").as_deref(),
+            Some("2023-11-14 22:13:20 +00:00")
+        );
+        assert_eq!(
+            convert("1700000000123 to date").as_deref(),
+            Some("2023-11-14 22:13:20 +00:00")
+        );
+    }
+
+    #[test]
+    fn other_queries_are_not_conversions() {
+        assert_eq!(convert("firefox"), None);
+        assert_eq!(convert("10 km in kg"), None);
+        assert_eq!(convert("km in mi"), None);
+        assert_eq!(convert("10 parsecs in mi"), None);
+        assert_eq!(convert("open in browser"), None);
+        assert_eq!(convert("to date"), None);
+    }
+}
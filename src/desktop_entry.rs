@@ -0,0 +1,565 @@
+// Group-aware parser for the freedesktop.org key-file format used by
+// .desktop files: https://specifications.freedesktop.org/desktop-entry-spec/latest/
+
+use std::env;
+
+pub struct KeyFile {
+    groups: Vec<Group>,
+}
+
+pub struct Group {
+    name: String,
+    entries: Vec<(String, String)>,
+}
+
+impl KeyFile {
+    pub fn parse(content: &str) -> KeyFile {
+        let mut groups: Vec<Group> = Vec::new();
+        // Index into `groups` of the group currently being filled, or None while
+        // we're before the first header or inside a duplicate group.
+        let mut current: Option<usize> = None;
+        let mut seen_header = false;
+
+        for line in content.lines() {
+            let line = line.trim_start();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                let Some(name) = line
+                    .trim_end()
+                    .strip_prefix('[')
+                    .and_then(|l| l.strip_suffix(']'))
+                else {
+                    continue;
+                };
+                seen_header = true;
+                // The spec forbids repeated groups; keep the first one and drop the rest.
+                if groups.iter().any(|g| g.name == name) {
+                    current = None;
+                } else {
+                    groups.push(Group {
+                        name: name.to_string(),
+                        entries: Vec::new(),
+                    });
+                    current = Some(groups.len() - 1);
+                }
+                continue;
+            }
+
+            if !seen_header {
+                continue;
+            }
+
+            let Some(index) = current else {
+                continue;
+            };
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim_end();
+            if key.is_empty() {
+                continue;
+            }
+
+            let group = &mut groups[index];
+            // Likewise for repeated keys: the first occurrence wins.
+            if !group.entries.iter().any(|(k, _)| k == key) {
+                group
+                    .entries
+                    .push((key.to_string(), value.trim().to_string()));
+            }
+        }
+
+        KeyFile { groups }
+    }
+
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = &Group> {
+        self.groups.iter()
+    }
+}
+
+impl Group {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn raw(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn string(&self, key: &str) -> Option<String> {
+        self.raw(key).map(unescape)
+    }
+
+    // Looks up `key[locale]` for each locale in preference order (as produced by
+    // `user_locales`) before falling back to the untranslated `key`.
+    pub fn locale_string(&self, key: &str, locales: &[String]) -> Option<String> {
+        self.locale_raw(key, locales).map(unescape)
+    }
+
+    pub fn locale_string_list(&self, key: &str, locales: &[String]) -> Vec<String> {
+        self.locale_raw(key, locales)
+            .map(split_list)
+            .unwrap_or_default()
+    }
+
+    fn locale_raw(&self, key: &str, locales: &[String]) -> Option<&str> {
+        locales
+            .iter()
+            .find_map(|locale| self.raw(&format!("{}[{}]", key, locale)))
+            .or_else(|| self.raw(key))
+    }
+
+    pub fn boolean(&self, key: &str) -> Option<bool> {
+        match self.raw(key)? {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn string_list(&self, key: &str) -> Vec<String> {
+        self.raw(key).map(split_list).unwrap_or_default()
+    }
+}
+
+// Locale keys to try for localized values, most preferred first. LANGUAGE is
+// honoured the way gettext does: only when the message locale isn't C/POSIX.
+pub fn user_locales() -> Vec<String> {
+    let message_locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .find_map(|var| env::var(var).ok().filter(|value| !value.is_empty()));
+    let language = env::var("LANGUAGE").ok();
+
+    locale_preferences(language.as_deref(), message_locale.as_deref())
+}
+
+fn locale_preferences(language: Option<&str>, message_locale: Option<&str>) -> Vec<String> {
+    let Some(message_locale) = message_locale else {
+        return Vec::new();
+    };
+    if is_c_locale(message_locale) {
+        return Vec::new();
+    }
+
+    let mut keys: Vec<String> = Vec::new();
+    let requested = language
+        .unwrap_or_default()
+        .split(':')
+        .chain(std::iter::once(message_locale))
+        .filter(|locale| !locale.is_empty() && !is_c_locale(locale));
+
+    for locale in requested {
+        for key in locale_variants(locale) {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+    }
+
+    keys
+}
+
+fn is_c_locale(locale: &str) -> bool {
+    let base = locale.split(['.', '@']).next().unwrap_or_default();
+    base == "C" || base == "POSIX"
+}
+
+// Expands `lang_COUNTRY.ENCODING@MODIFIER` into the keys the spec says to try, in
+// order: lang_COUNTRY@MODIFIER, lang_COUNTRY, lang@MODIFIER, lang. The encoding is
+// never part of the match.
+fn locale_variants(locale: &str) -> Vec<String> {
+    let (base, modifier) = match locale.split_once('@') {
+        Some((base, modifier)) => (base, Some(modifier)),
+        None => (locale, None),
+    };
+    let base = base.split('.').next().unwrap_or_default();
+    let (lang, country) = match base.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (base, None),
+    };
+
+    if lang.is_empty() {
+        return Vec::new();
+    }
+
+    let mut variants = Vec::new();
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        variants.push(format!("{}_{}@{}", lang, country, modifier));
+    }
+    if let Some(country) = country {
+        variants.push(format!("{}_{}", lang, country));
+    }
+    if let Some(modifier) = modifier {
+        variants.push(format!("{}@{}", lang, modifier));
+    }
+    variants.push(lang.to_string());
+    variants
+}
+
+// Inverse of `unescape`, for writing string values back out. Leading and
+// trailing spaces are escaped because they'd otherwise be trimmed on read.
+pub fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let last = value.chars().count().saturating_sub(1);
+
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            ' ' if i == 0 || i == last => out.push_str("\\s"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\\' => out.push_str("\\\\"),
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+pub fn join_list(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| format!("{};", escape(item).replace(';', "\\;")))
+        .collect()
+}
+
+// Rewrites `key` in `group` of an existing key file's text, leaving every other
+// line (comments included) untouched. The key is appended to the group if
+// missing, and the group to the file if that's missing too. `value` must
+// already be escaped.
+pub fn set_value(content: &str, group: &str, key: &str, value: &str) -> String {
+    let new_line = format!("{}={}", key, value);
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+
+    let mut in_group = false;
+    // Index just past the last non-blank line of the target group.
+    let mut group_end = None;
+
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            in_group = trimmed == format!("[{}]", group);
+            if in_group {
+                group_end = Some(i + 1);
+            }
+            continue;
+        }
+        if !in_group || trimmed.is_empty() {
+            continue;
+        }
+        group_end = Some(i + 1);
+        if !trimmed.starts_with('#') && trimmed.split_once('=').map(|(k, _)| k.trim()) == Some(key)
+        {
+            lines[i] = new_line;
+            return lines.join("\n") + "\n";
+        }
+    }
+
+    match group_end {
+        Some(index) => lines.insert(index, new_line),
+        None => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(format!("[{}]", group));
+            lines.push(new_line);
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+
+    out
+}
+
+// Splits a `;`-separated list value. `\;` is a literal semicolon inside an item
+// and the trailing separator is optional.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(';') => current.push(';'),
+                Some(other) => {
+                    current.push('\\');
+                    current.push(other);
+                }
+                None => current.push('\\'),
+            },
+            ';' => items.push(unescape(&std::mem::take(&mut current))),
+            _ => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        items.push(unescape(&current));
+    }
+
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_keep_their_own_keys() {
+        let file = KeyFile::parse(
+            "[Desktop Entry]\nName=Firefox\nExec=firefox %u\n\n\
+             [Desktop Action new-window]\nName=New Window\nExec=firefox --new-window %u\n",
+        );
+
+        let entry = file.group("Desktop Entry").unwrap();
+        assert_eq!(entry.string("Name").as_deref(), Some("Firefox"));
+        assert_eq!(entry.string("Exec").as_deref(), Some("firefox %u"));
+
+        let action = file.group("Desktop Action new-window").unwrap();
+        assert_eq!(action.string("Name").as_deref(), Some("New Window"));
+    }
+
+    #[test]
+    fn comments_blank_lines_and_preamble_are_ignored() {
+        let file = KeyFile::parse(
+            "# Name=Commented\nName=Before any group\n\n[Desktop Entry]\n  # NoDisplay=true\nName=Real\n",
+        );
+
+        let entry = file.group("Desktop Entry").unwrap();
+        assert_eq!(entry.string("Name").as_deref(), Some("Real"));
+        assert_eq!(entry.raw("NoDisplay"), None);
+        assert_eq!(entry.raw("# NoDisplay"), None);
+    }
+
+    #[test]
+    fn whitespace_around_equals_is_ignored() {
+        let file = KeyFile::parse("[Desktop Entry]\nName = Spaced Out  \nIcon=\tterminal\n");
+        let entry = file.group("Desktop Entry").unwrap();
+        assert_eq!(entry.string("Name").as_deref(), Some("Spaced Out"));
+        assert_eq!(entry.string("Icon").as_deref(), Some("terminal"));
+    }
+
+    #[test]
+    fn escape_sequences_are_decoded() {
+        let file = KeyFile::parse(
+            "[Desktop Entry]\nComment=Line one\\nLine two\\tTabbed\\sSpace\\\\Backslash\\q\n\
+             Name=\\sLeading space\n",
+        );
+        let entry = file.group("Desktop Entry").unwrap();
+        assert_eq!(
+            entry.string("Comment").as_deref(),
+            Some("Line one\nLine two\tTabbed Space\\Backslash\\q")
+        );
+        assert_eq!(entry.string("Name").as_deref(), Some(" Leading space"));
+    }
+
+    #[test]
+    fn list_values_split_on_unescaped_semicolons() {
+        let file = KeyFile::parse(
+            "[Desktop Entry]\nKeywords=web;browser\\;internet;;\nCategories=Network;WebBrowser\n\
+             MimeType=\n",
+        );
+        let entry = file.group("Desktop Entry").unwrap();
+        assert_eq!(
+            entry.string_list("Keywords"),
+            vec!["web", "browser;internet"]
+        );
+        assert_eq!(
+            entry.string_list("Categories"),
+            vec!["Network", "WebBrowser"]
+        );
+        assert!(entry.string_list("MimeType").is_empty());
+        assert!(entry.string_list("Missing").is_empty());
+    }
+
+    #[test]
+    fn booleans_only_accept_spec_values() {
+        let file = KeyFile::parse("[Desktop Entry]\nNoDisplay=true\nHidden=false\nTerminal=yes\n");
+        let entry = file.group("Desktop Entry").unwrap();
+        assert_eq!(entry.boolean("NoDisplay"), Some(true));
+        assert_eq!(entry.boolean("Hidden"), Some(false));
+        assert_eq!(entry.boolean("Terminal"), None);
+    }
+
+    #[test]
+    fn first_occurrence_of_group_and_key_wins() {
+        let file = KeyFile::parse(
+            "[Desktop Entry]\nName=First\nName=Second\n[Desktop Entry]\nExec=ignored\n",
+        );
+        let entry = file.group("Desktop Entry").unwrap();
+        assert_eq!(entry.string("Name").as_deref(), Some("First"));
+        assert_eq!(entry.raw("Exec"), None);
+        assert_eq!(file.groups.len(), 1);
+    }
+
+    #[test]
+    fn localized_keys_are_distinct_from_the_plain_key() {
+        let file = KeyFile::parse("[Desktop Entry]\nName[de]=Rechner\nName=Calculator\n");
+        let entry = file.group("Desktop Entry").unwrap();
+        assert_eq!(entry.string("Name").as_deref(), Some("Calculator"));
+        assert_eq!(entry.string("Name[de]").as_deref(), Some("Rechner"));
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        let file = KeyFile::parse(
+            "[Desktop Entry\nName=Orphan\n[Desktop Entry]\njust some text\n=novalue\nName=Kept\n",
+        );
+        let entry = file.group("Desktop Entry").unwrap();
+        assert_eq!(entry.string("Name").as_deref(), Some("Kept"));
+    }
+
+    #[test]
+    fn locale_variants_follow_spec_order() {
+        assert_eq!(
+            locale_variants("sr_YU.UTF-8@Latn"),
+            vec!["sr_YU@Latn", "sr_YU", "sr@Latn", "sr"]
+        );
+        assert_eq!(locale_variants("de_DE.UTF-8"), vec!["de_DE", "de"]);
+        assert_eq!(locale_variants("ja"), vec!["ja"]);
+    }
+
+    #[test]
+    fn language_list_takes_priority_over_message_locale() {
+        assert_eq!(
+            locale_preferences(Some("ja_JP:de"), Some("en_US.UTF-8")),
+            vec!["ja_JP", "ja", "de", "en_US", "en"]
+        );
+        assert_eq!(
+            locale_preferences(None, Some("de_AT.UTF-8")),
+            vec!["de_AT", "de"]
+        );
+    }
+
+    #[test]
+    fn c_locale_disables_translations() {
+        assert!(locale_preferences(Some("de"), Some("C.UTF-8")).is_empty());
+        assert!(locale_preferences(Some("de"), None).is_empty());
+    }
+
+    #[test]
+    fn locale_string_picks_most_specific_translation() {
+        let file = KeyFile::parse(
+            "[Desktop Entry]\nName=Calculator\nName[de]=Rechner\nName[de_AT]=Taschenrechner\n\
+             Name[sr@Latn]=Kalkulator\nComment[ja]=\\s計算機\n",
+        );
+        let entry = file.group("Desktop Entry").unwrap();
+
+        let austria = locale_preferences(None, Some("de_AT.UTF-8"));
+        assert_eq!(
+            entry.locale_string("Name", &austria).as_deref(),
+            Some("Taschenrechner")
+        );
+
+        let germany = locale_preferences(None, Some("de_DE.UTF-8"));
+        assert_eq!(
+            entry.locale_string("Name", &germany).as_deref(),
+            Some("Rechner")
+        );
+
+        let serbian = locale_preferences(None, Some("sr_RS@Latn"));
+        assert_eq!(
+            entry.locale_string("Name", &serbian).as_deref(),
+            Some("Kalkulator")
+        );
+
+        let japanese = locale_preferences(None, Some("ja_JP.UTF-8"));
+        assert_eq!(
+            entry.locale_string("Name", &japanese).as_deref(),
+            Some("Calculator")
+        );
+        assert_eq!(
+            entry.locale_string("Comment", &japanese).as_deref(),
+            Some(" 計算機")
+        );
+        assert_eq!(entry.locale_string("Comment", &[]), None);
+    }
+
+    #[test]
+    fn locale_string_list_uses_translated_list() {
+        let file = KeyFile::parse(
+            "[Desktop Entry]\nKeywords=calculator;math;\nKeywords[de]=Rechner;Mathe;\n",
+        );
+        let entry = file.group("Desktop Entry").unwrap();
+        let german = locale_preferences(None, Some("de_DE"));
+        assert_eq!(
+            entry.locale_string_list("Keywords", &german),
+            vec!["Rechner", "Mathe"]
+        );
+        assert_eq!(
+            entry.locale_string_list("Keywords", &[]),
+            vec!["calculator", "math"]
+        );
+    }
+
+    #[test]
+    fn escape_round_trips_through_parse() {
+        let value = " two\nlines\twith\\slashes ";
+        let items = vec!["a;b".to_string(), " c".to_string()];
+        let content = format!("[G]\nKey={}\nList={}\n", escape(value), join_list(&items));
+        let file = KeyFile::parse(&content);
+        let group = file.groups().next().unwrap();
+        assert_eq!(group.name(), "G");
+        assert_eq!(group.string("Key").as_deref(), Some(value));
+        assert_eq!(group.string_list("List"), items);
+    }
+
+    #[test]
+    fn set_value_replaces_in_place_and_keeps_comments() {
+        let content =
+            "# My settings\n[General]\nTerminal=foot\n\n[Pinned]\n# favourites\nApps=a.desktop;\n";
+        assert_eq!(
+            set_value(content, "Pinned", "Apps", "b.desktop;a.desktop;"),
+            "# My settings\n[General]\nTerminal=foot\n\n[Pinned]\n# favourites\nApps=b.desktop;a.desktop;\n"
+        );
+    }
+
+    #[test]
+    fn set_value_adds_missing_key_and_group() {
+        let content = "[General]\nTerminal=foot\n\n[Other]\nX=1\n";
+        assert_eq!(
+            set_value(content, "General", "Shell", "zsh"),
+            "[General]\nTerminal=foot\nShell=zsh\n\n[Other]\nX=1\n"
+        );
+        assert_eq!(
+            set_value(content, "Pinned", "Apps", "a;"),
+            "[General]\nTerminal=foot\n\n[Other]\nX=1\n\n[Pinned]\nApps=a;\n"
+        );
+        assert_eq!(set_value("", "Pinned", "Apps", "a;"), "[Pinned]\nApps=a;\n");
+    }
+}
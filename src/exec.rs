@@ -0,0 +1,203 @@
+// Tokenizes and expands the Exec key of a desktop entry following the
+// "The Exec key" section of the Desktop Entry spec.
+
+pub struct ExecContext<'a> {
+    pub name: &'a str,
+    pub icon: Option<&'a str>,
+    pub desktop_file: &'a str,
+}
+
+// Splits an (already key-file-unescaped) Exec value into arguments. Double
+// quotes group an argument; inside them `\"`, `` \` ``, `\$` and `\\` escape
+// the quoted character.
+pub fn tokenize(exec: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut chars = exec.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped @ ('"' | '`' | '$' | '\\')) => current.push(escaped),
+                            Some(other) => {
+                                current.push('\\');
+                                current.push(other);
+                            }
+                            None => return Err("unterminated quote in Exec".to_string()),
+                        },
+                        Some(other) => current.push(other),
+                        None => return Err("unterminated quote in Exec".to_string()),
+                    }
+                }
+            }
+            _ => {
+                in_arg = true;
+                current.push(c);
+            }
+        }
+    }
+
+    if in_arg {
+        args.push(current);
+    }
+
+    Ok(args)
+}
+
+// Turns an Exec value into the argv to spawn. We never pass files or URLs, so
+// %f/%F/%u/%U expand to nothing, and arguments that consisted only of such a
+// code are dropped entirely rather than left as empty strings.
+pub fn expand(exec: &str, context: &ExecContext) -> Result<Vec<String>, String> {
+    let mut argv = Vec::new();
+
+    for arg in tokenize(exec)? {
+        match arg.as_str() {
+            "%i" => {
+                if let Some(icon) = context.icon.filter(|icon| !icon.is_empty()) {
+                    argv.push("--icon".to_string());
+                    argv.push(icon.to_string());
+                }
+            }
+            "%f" | "%F" | "%u" | "%U" | "%d" | "%D" | "%n" | "%N" | "%v" | "%m" => {}
+            _ => argv.push(expand_arg(&arg, context)?),
+        }
+    }
+
+    if argv.is_empty() {
+        return Err("Exec is empty".to_string());
+    }
+
+    Ok(argv)
+}
+
+fn expand_arg(arg: &str, context: &ExecContext) -> Result<String, String> {
+    let mut out = String::with_capacity(arg.len());
+    let mut chars = arg.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => out.push('%'),
+            Some('c') => out.push_str(context.name),
+            Some('k') => out.push_str(context.desktop_file),
+            Some('i') => {
+                if let Some(icon) = context.icon {
+                    out.push_str(icon);
+                }
+            }
+            // File/URL codes and the deprecated ones expand to nothing.
+            Some('f' | 'F' | 'u' | 'U' | 'd' | 'D' | 'n' | 'N' | 'v' | 'm') => {}
+            Some(other) => return Err(format!("invalid field code %{} in Exec", other)),
+            None => return Err("dangling % in Exec".to_string()),
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> ExecContext<'static> {
+        ExecContext {
+            name: "Text Editor",
+            icon: Some("accessories-text-editor"),
+            desktop_file: "/usr/share/applications/org.gnome.TextEditor.desktop",
+        }
+    }
+
+    #[test]
+    fn splits_on_whitespace() {
+        assert_eq!(
+            tokenize("firefox  --new-window\tabout:blank").unwrap(),
+            vec!["firefox", "--new-window", "about:blank"]
+        );
+    }
+
+    #[test]
+    fn quoted_arguments_keep_spaces_and_escapes() {
+        assert_eq!(
+            tokenize(r#""/opt/My App/bin/app" --title "say \"hi\" for \$5 \\ \`now\`" """#)
+                .unwrap(),
+            vec![
+                "/opt/My App/bin/app",
+                "--title",
+                "say \"hi\" for $5 \\ `now`",
+                ""
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_quote_is_an_error() {
+        assert!(tokenize("app \"oops").is_err());
+    }
+
+    #[test]
+    fn file_and_url_codes_are_dropped() {
+        assert_eq!(expand("firefox %u", &context()).unwrap(), vec!["firefox"]);
+        assert_eq!(
+            expand("app %F --flag %U", &context()).unwrap(),
+            vec!["app", "--flag"]
+        );
+    }
+
+    #[test]
+    fn icon_name_and_desktop_file_codes_expand() {
+        assert_eq!(
+            expand("editor %i --class=%c %k", &context()).unwrap(),
+            vec![
+                "editor",
+                "--icon",
+                "accessories-text-editor",
+                "--class=Text Editor",
+                "/usr/share/applications/org.gnome.TextEditor.desktop"
+            ]
+        );
+
+        let no_icon = ExecContext {
+            icon: None,
+            ..context()
+        };
+        assert_eq!(expand("editor %i", &no_icon).unwrap(), vec!["editor"]);
+    }
+
+    #[test]
+    fn percent_escape_and_deprecated_codes() {
+        assert_eq!(
+            expand("printf 100%% %d %n", &context()).unwrap(),
+            vec!["printf", "100%"]
+        );
+    }
+
+    #[test]
+    fn quoted_field_code_is_expanded_inside_its_argument() {
+        assert_eq!(
+            expand(r#"sh -c "echo %c""#, &context()).unwrap(),
+            vec!["sh", "-c", "echo Text Editor"]
+        );
+    }
+
+    #[test]
+    fn invalid_codes_refuse_to_launch() {
+        assert!(expand("app %z", &context()).is_err());
+        assert!(expand("app 50%", &context()).is_err());
+        assert!(expand("%u", &context()).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use gtk4::prelude::*;
+use gtk4::{gio, glib};
+use std::path::Path;
+use std::thread;
+
+use crate::files::{self, FileIndex};
+use crate::history;
+
+// Hands the file index to `on_update` without blocking the main thread: the
+// cached index is loaded on a worker thread, and if it is missing, stale or
+// was built for other roots a fresh one is built there too and sent after it.
+pub fn start_indexing(on_update: impl Fn(FileIndex) + 'static) {
+    let (sender, receiver) = async_channel::unbounded();
+
+    thread::spawn(move || {
+        let roots = files::file_roots();
+        let cached = FileIndex::load(&roots);
+        let stale = cached
+            .as_ref()
+            .is_none_or(|index| index.is_stale(history::now()));
+        if let Some(index) = cached {
+            let _ = sender.send_blocking(index);
+        }
+        if stale {
+            let index = FileIndex::scan(&roots);
+            let _ = index.save();
+            let _ = sender.send_blocking(index);
+        }
+    });
+
+    glib::spawn_future_local(async move {
+        while let Ok(index) = receiver.recv().await {
+            on_update(index);
+        }
+    });
+}
+
+// Shows `path` selected in its folder through the FileManager1 D-Bus interface
+// (Nautilus, Dolphin, Nemo, Thunar...), or opens the folder itself when no file
+// manager provides it. The call is asynchronous, and holds the application so
+// it outlives the popup closing until the file manager has answered.
+pub fn reveal(path: &Path) {
+    let uri = gio::File::for_path(path).uri().to_string();
+    let folder = path.parent().unwrap_or(path).to_path_buf();
+    let hold = gio::Application::default().map(|app| app.hold());
+
+    gio::bus_get(
+        gio::BusType::Session,
+        gio::Cancellable::NONE,
+        move |connection| {
+            let Ok(connection) = connection else {
+                open_folder(&folder);
+                drop(hold);
+                return;
+            };
+            connection.call(
+                Some("org.freedesktop.FileManager1"),
+                "/org/freedesktop/FileManager1",
+                "org.freedesktop.FileManager1",
+                "ShowItems",
+                Some(&(vec![uri], "").to_variant()),
+                None,
+                gio::DBusCallFlags::NONE,
+                2000,
+                gio::Cancellable::NONE,
+                move |shown| {
+                    if shown.is_err() {
+                        open_folder(&folder);
+                    }
+                    drop(hold);
+                },
+            );
+        },
+    );
+}
+
+// The popup is gone by the time this runs, so errors can only be logged.
+fn open_folder(folder: &Path) {
+    if let Err(err) = files::open(folder) {
+        eprintln!("{}", err);
+    }
+}
@@ -0,0 +1,406 @@
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::apps::{find_executable, search_path, spawn_detached};
+use crate::config::Config;
+use crate::fuzzy;
+use crate::history;
+
+// Bumped whenever the index layout changes so stale caches are rebuilt.
+const INDEX_VERSION: u32 = 1;
+
+// An index younger than this is used as-is; older ones are shown while a fresh
+// one is built in the background.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+// Bounds on the walk, so a root like / or a huge checkout can't take the
+// index (and the cache file) with it.
+const MAX_DEPTH: usize = 12;
+const MAX_ENTRIES: usize = 200_000;
+
+// Build output and dependency trees: many files, none worth opening from here.
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target", "__pycache__"];
+
+const MAX_RESULTS: usize = 50;
+
+// Searches starting with one of these look for files, e.g. "~/notes".
+pub const PREFIXES: &[&str] = &["/", "~"];
+
+// The names of files and directories under the configured roots, cached in
+// $XDG_CACHE_HOME/bitpop/files.json. Hidden entries aren't indexed, and
+// symlinks are listed but never followed.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct FileIndex {
+    version: u32,
+    roots: Vec<PathBuf>,
+    built: i64,
+    entries: Vec<FileEntry>,
+    // The query before and the entries it matched, which are all a query
+    // that extends it can match: "abc" only fuzzy-matches where "ab" does.
+    // Typing a query one character at a time only scans the whole index once.
+    #[serde(skip)]
+    narrowed: RefCell<Option<(String, Vec<usize>)>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
+
+impl FileEntry {
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.path.to_string_lossy().into_owned())
+    }
+}
+
+impl FileIndex {
+    pub fn load(roots: &[PathBuf]) -> Option<FileIndex> {
+        FileIndex::load_from(&index_path()?, roots)
+    }
+
+    fn load_from(path: &Path, roots: &[PathBuf]) -> Option<FileIndex> {
+        let content = fs::read(path).ok()?;
+        let index: FileIndex = serde_json::from_slice(&content).ok()?;
+        (index.version == INDEX_VERSION && index.roots == roots).then_some(index)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        self.save_to(&index_path().ok_or(io::ErrorKind::NotFound)?)
+    }
+
+    fn save_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = serde_json::to_vec(self).map_err(io::Error::other)?;
+
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(tmp_path, path)
+    }
+
+    // Walks every root breadth-first, so when the entry limit is hit it is the
+    // deepest files that are left out.
+    pub fn scan(roots: &[PathBuf]) -> FileIndex {
+        let mut entries = Vec::new();
+        let mut queue: VecDeque<(PathBuf, usize)> =
+            roots.iter().map(|root| (root.clone(), 0)).collect();
+
+        while let Some((dir, depth)) = queue.pop_front() {
+            let Ok(read_dir) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in read_dir.flatten() {
+                if entries.len() >= MAX_ENTRIES {
+                    break;
+                }
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                if name.starts_with('.') {
+                    continue;
+                }
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+
+                let path = entry.path();
+                let is_dir = file_type.is_dir();
+                if is_dir
+                    && depth + 1 < MAX_DEPTH
+                    && !SKIPPED_DIRS.contains(&name.as_ref())
+                    && !roots.contains(&path)
+                {
+                    queue.push_back((path.clone(), depth + 1));
+                }
+                entries.push(FileEntry { path, is_dir });
+            }
+        }
+
+        FileIndex {
+            version: INDEX_VERSION,
+            roots: roots.to_vec(),
+            built: history::now(),
+            entries,
+            narrowed: RefCell::default(),
+        }
+    }
+
+    pub fn is_stale(&self, now: i64) -> bool {
+        now - self.built >= REFRESH_INTERVAL.as_secs() as i64
+    }
+
+    // Queries without a slash match file names; with one they match the path
+    // below the root, so "proj/read" finds ~/projects/bitpop/README.md. Best
+    // matches come first, then shallower paths.
+    pub fn search(&self, query: &str) -> Vec<FileEntry> {
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let by_path = query.contains('/');
+        let lower_query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+        let candidates = match self.narrowed.take() {
+            Some((previous, matched))
+                if query.starts_with(&previous) && previous.contains('/') == by_path =>
+            {
+                matched
+            }
+            _ => (0..self.entries.len()).collect(),
+        };
+
+        let mut matched = Vec::new();
+        let mut scored: Vec<(i32, &FileEntry)> = Vec::new();
+        for index in candidates {
+            let entry = &self.entries[index];
+            let text = if by_path {
+                self.relative_path(&entry.path).to_string_lossy()
+            } else {
+                entry
+                    .path
+                    .file_name()
+                    .unwrap_or(entry.path.as_os_str())
+                    .to_string_lossy()
+            };
+            // Far cheaper than scoring, and rules out most of the index
+            if !is_subsequence(&lower_query, &text) {
+                continue;
+            }
+            matched.push(index);
+            if let Some(score) = fuzzy::score(&text, query) {
+                scored.push((score, entry));
+            }
+        }
+        *self.narrowed.borrow_mut() = Some((query.to_string(), matched));
+
+        scored.sort_by(|(a_score, a), (b_score, b)| {
+            b_score
+                .cmp(a_score)
+                .then_with(|| {
+                    a.path
+                        .components()
+                        .count()
+                        .cmp(&b.path.components().count())
+                })
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        scored
+            .into_iter()
+            .take(MAX_RESULTS)
+            .map(|(_, entry)| entry.clone())
+            .collect()
+    }
+
+    fn relative_path<'a>(&self, path: &'a Path) -> &'a Path {
+        self.roots
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok())
+            .unwrap_or(path)
+    }
+}
+
+// Whether the characters of `query` (already lowercase) appear in `text` in
+// order, ignoring case the way fuzzy::score does.
+fn is_subsequence(query: &[char], text: &str) -> bool {
+    let mut query = query.iter().peekable();
+    for c in text.chars() {
+        let Some(next) = query.peek() else {
+            break;
+        };
+        if c.to_lowercase().next().unwrap_or(c) == **next {
+            query.next();
+        }
+    }
+    query.peek().is_none()
+}
+
+// The configured roots, or just the home directory.
+pub fn file_roots() -> Vec<PathBuf> {
+    let roots = Config::load().file_roots;
+    if !roots.is_empty() {
+        return roots;
+    }
+    dirs::home_dir().into_iter().collect()
+}
+
+// The file name query of a search starting with "/" or "~" (like "~/notes"),
+// or None for ordinary searches.
+pub fn file_query(text: &str) -> Option<&str> {
+    let text = text.trim_start();
+    if !PREFIXES.iter().any(|prefix| text.starts_with(prefix)) {
+        return None;
+    }
+    Some(text.trim_start_matches('~').trim_start_matches('/'))
+}
+
+// `path` with the home directory shortened to "~".
+pub fn display_path(path: &Path, home: Option<&Path>) -> String {
+    match home.and_then(|home| path.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => path.display().to_string(),
+    }
+}
+
+// Opens `path` with its default application through xdg-open, or gio where
+// xdg-open isn't installed.
+pub fn open(path: &Path) -> Result<(), String> {
+    let target = path.to_string_lossy().into_owned();
+    let argv = if find_executable("xdg-open", &search_path()).is_some() {
+        vec!["xdg-open".to_string(), target]
+    } else {
+        vec!["gio".to_string(), "open".to_string(), target]
+    };
+
+    spawn_detached(&argv, None).map_err(|err| format!("Couldn't open {}: {}", path.display(), err))
+}
+
+fn index_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("bitpop").join("files.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("bitpop-files-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn touch(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "").unwrap();
+    }
+
+    fn names(results: &[FileEntry]) -> Vec<String> {
+        results.iter().map(FileEntry::name).collect()
+    }
+
+    #[test]
+    fn prefixes_start_a_file_search() {
+        assert_eq!(file_query("/notes"), Some("notes"));
+        assert_eq!(file_query("~/proj/read"), Some("proj/read"));
+        assert_eq!(file_query("~"), Some(""));
+        assert_eq!(file_query("notes"), None);
+
+        let home = Path::new("/home/me");
+        assert_eq!(
+            display_path(Path::new("/home/me/docs"), Some(home)),
+            "~/docs"
+        );
+        assert_eq!(display_path(home, Some(home)), "~");
+        assert_eq!(display_path(Path::new("/etc"), Some(home)), "/etc");
+    }
+
+    #[test]
+    fn scan_skips_hidden_and_build_directories() {
+        let root = temp_root("scan");
+        touch(&root.join("notes.txt"));
+        touch(&root.join("projects/bitpop/README.md"));
+        touch(&root.join("projects/bitpop/target/debug/bitpop"));
+        touch(&root.join(".cache/junk"));
+        touch(&root.join(".hidden"));
+
+        let index = FileIndex::scan(std::slice::from_ref(&root));
+        let mut paths: Vec<(String, bool)> = index
+            .entries
+            .iter()
+            .map(|entry| {
+                let relative = entry.path.strip_prefix(&root).unwrap();
+                (relative.display().to_string(), entry.is_dir)
+            })
+            .collect();
+        paths.sort();
+
+        assert_eq!(
+            paths,
+            vec![
+                ("notes.txt".to_string(), false),
+                ("projects".to_string(), true),
+                ("projects/bitpop".to_string(), true),
+                ("projects/bitpop/README.md".to_string(), false),
+                ("projects/bitpop/target".to_string(), true),
+            ]
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn search_matches_names_or_paths_below_the_root() {
+        let root = temp_root("search");
+        touch(&root.join("readme.txt"));
+        touch(&root.join("projects/bitpop/README.md"));
+        touch(&root.join("projects/other/src/reader.rs"));
+
+        let index = FileIndex::scan(std::slice::from_ref(&root));
+
+        // Equal name matches: the shallower path wins
+        assert_eq!(
+            names(&index.search("readme")),
+            vec!["readme.txt", "README.md"]
+        );
+        assert_eq!(names(&index.search("proj/bit/read")), vec!["README.md"]);
+        assert!(index.search("").is_empty());
+        assert!(index.search("nothing").is_empty());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn typing_on_narrows_the_previous_matches() {
+        let root = temp_root("narrow");
+        touch(&root.join("readme.txt"));
+        touch(&root.join("projects/bitpop/README.md"));
+        touch(&root.join("projects/other/src/reader.rs"));
+        touch(&root.join("notes.md"));
+
+        let index = FileIndex::scan(std::slice::from_ref(&root));
+        let fresh = |query: &str| {
+            let unnarrowed = FileIndex {
+                narrowed: RefCell::default(),
+                ..index.clone()
+            };
+            names(&unnarrowed.search(query))
+        };
+
+        for query in [
+            "r", "re", "rea", "read", "reade", "re", "rd", "proj", "proj/", "proj/o",
+        ] {
+            assert_eq!(names(&index.search(query)), fresh(query), "{:?}", query);
+        }
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn cached_index_is_only_used_for_the_same_roots() {
+        let root = temp_root("cache");
+        touch(&root.join("home/notes.txt"));
+        let roots = vec![root.join("home")];
+        let cache = root.join("files.json");
+
+        let index = FileIndex::scan(&roots);
+        index.save_to(&cache).unwrap();
+
+        let loaded = FileIndex::load_from(&cache, &roots).unwrap();
+        assert_eq!(loaded.entries, index.entries);
+        assert!(!loaded.is_stale(loaded.built + 60));
+        assert!(loaded.is_stale(loaded.built + REFRESH_INTERVAL.as_secs() as i64));
+
+        assert!(FileIndex::load_from(&cache, &[root.join("elsewhere")]).is_none());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+// Subsequence-based fuzzy scorer for launcher search. Every query character
+// must appear in order in the text; the best-scoring alignment is chosen with
+// bonuses for matches at the start of the text, at word starts (so acronyms
+// like "vsc" find "Visual Studio Code") and for runs of consecutive characters.
+
+const SCORE_MATCH: i32 = 16;
+const BONUS_FIRST_CHAR: i32 = 24;
+const BONUS_WORD_START: i32 = 20;
+const BONUS_CAMEL_CASE: i32 = 18;
+const BONUS_CONSECUTIVE: i32 = 12;
+const PENALTY_GAP: i32 = 1;
+const MAX_LEADING_PENALTY: i32 = 8;
+
+const UNMATCHED: i32 = i32::MIN / 2;
+
+// Returns None when `query` isn't a subsequence of `text` (case-insensitive),
+// otherwise a score where higher is better. An empty query scores 0.
+pub fn score(text: &str, query: &str) -> Option<i32> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let text: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = text
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    if query.len() > text.len() {
+        return None;
+    }
+
+    let bonus: Vec<i32> = (0..text.len()).map(|j| position_bonus(&text, j)).collect();
+
+    // matched[j]: best score for the query so far with its last char at text[j].
+    // best[j]: best score with the last char anywhere in text[..=j], minus a gap
+    // penalty for every character since.
+    let mut prev_matched = vec![UNMATCHED; text.len()];
+    let mut prev_best = vec![UNMATCHED; text.len()];
+
+    for (i, &qc) in query.iter().enumerate() {
+        let mut matched = vec![UNMATCHED; text.len()];
+        let mut best = vec![UNMATCHED; text.len()];
+
+        for j in 0..text.len() {
+            if lower[j] == qc {
+                if i == 0 {
+                    let leading = (j as i32 * PENALTY_GAP).min(MAX_LEADING_PENALTY);
+                    matched[j] = SCORE_MATCH + bonus[j] - leading;
+                } else if j > 0 {
+                    let consecutive =
+                        prev_matched[j - 1] + SCORE_MATCH + bonus[j].max(BONUS_CONSECUTIVE);
+                    let gapped = prev_best[j - 1] + SCORE_MATCH + bonus[j];
+                    matched[j] = consecutive.max(gapped);
+                }
+            }
+
+            let carried = if j > 0 {
+                best[j - 1] - PENALTY_GAP
+            } else {
+                UNMATCHED
+            };
+            best[j] = matched[j].max(carried);
+        }
+
+        prev_matched = matched;
+        prev_best = best;
+    }
+
+    prev_matched
+        .into_iter()
+        .max()
+        .filter(|score| *score > UNMATCHED / 2)
+}
+
+fn position_bonus(text: &[char], j: usize) -> i32 {
+    if j == 0 {
+        return BONUS_FIRST_CHAR;
+    }
+    let (prev, current) = (text[j - 1], text[j]);
+    if !prev.is_alphanumeric() && current.is_alphanumeric() {
+        BONUS_WORD_START
+    } else if prev.is_lowercase() && current.is_uppercase() {
+        BONUS_CAMEL_CASE
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requires_an_in_order_subsequence() {
+        assert!(score("Firefox", "ff").is_some());
+        assert!(score("Firefox", "FOX").is_some());
+        assert!(score("Firefox", "xof").is_none());
+        assert!(score("Fx", "fxx").is_none());
+        assert_eq!(score("Anything", ""), Some(0));
+    }
+
+    #[test]
+    fn prefix_beats_word_start_beats_middle() {
+        let prefix = score("Terminal", "term").unwrap();
+        let word = score("GNOME Terminal", "term").unwrap();
+        let middle = score("Alacritty Determinator", "term").unwrap();
+        assert!(prefix > word, "{} > {}", prefix, word);
+        assert!(word > middle, "{} > {}", word, middle);
+    }
+
+    #[test]
+    fn acronyms_beat_scattered_matches() {
+        let acronym = score("Visual Studio Code", "vsc").unwrap();
+        let scattered = score("Volume Mixer Control", "vsc").unwrap_or(i32::MIN);
+        let camel = score("VirtualBoxStudioConsole", "vsc").unwrap();
+        assert!(acronym > scattered);
+        assert!(camel > scattered);
+    }
+
+    #[test]
+    fn consecutive_run_beats_spread_out_letters() {
+        let run = score("Calculator", "calc").unwrap();
+        let spread = score("Character Map Launcher", "calc").unwrap();
+        assert!(run > spread);
+    }
+
+    #[test]
+    fn picks_the_best_alignment_not_the_first() {
+        // Greedily taking the first "c" and "o" of "Cisco" would miss the
+        // contiguous word-start match on "Code".
+        let best = score("Cisco Code", "code").unwrap();
+        let word_only = score("Xxxxx Code", "code").unwrap();
+        assert_eq!(best, word_only);
+    }
+
+    #[test]
+    fn matching_is_case_insensitive_and_unicode_aware() {
+        assert!(score("ÉDITEUR de texte", "édi").is_some());
+        assert!(score("Texteditor", "TEXT").is_some());
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::desktop_entry::KeyFile;
+
+// Launch counts decay exponentially: a launch a week ago is worth half of one
+// today, so apps you stopped using sink without ever being forgotten outright.
+const HALF_LIFE_SECS: f64 = 7.0 * 24.0 * 60.0 * 60.0;
+
+// Below this an entry is worth less than 1% of a single fresh launch and is
+// dropped on save.
+const MIN_SCORE: f64 = 0.01;
+
+// Frecency (frequency + recency) of launches per desktop file ID, stored in
+// $XDG_STATE_HOME/bitpop/history as a key file:
+//
+//   [firefox.desktop]
+//   Score=3.42
+//   LastUsed=1760000000
+#[derive(Default)]
+pub struct History {
+    entries: HashMap<String, Usage>,
+}
+
+struct Usage {
+    score: f64,
+    last_used: i64,
+}
+
+impl History {
+    pub fn load() -> History {
+        let Some(content) = history_path().and_then(|path| fs::read_to_string(path).ok()) else {
+            return History::default();
+        };
+
+        let entries = KeyFile::parse(&content)
+            .groups()
+            .filter_map(|group| {
+                let score = group.raw("Score")?.parse().ok()?;
+                let last_used = group.raw("LastUsed")?.parse().ok()?;
+                Some((group.name().to_string(), Usage { score, last_used }))
+            })
+            .collect();
+
+        History { entries }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = history_path().ok_or(io::ErrorKind::NotFound)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let now = now();
+        let mut ids: Vec<&String> = self
+            .entries
+            .keys()
+            .filter(|id| self.frecency(id, now) >= MIN_SCORE)
+            .collect();
+        ids.sort();
+
+        let mut content = String::new();
+        for id in ids {
+            let usage = &self.entries[id];
+            content.push_str(&format!(
+                "[{}]\nScore={:.4}\nLastUsed={}\n\n",
+                id, usage.score, usage.last_used
+            ));
+        }
+
+        // Write-then-rename so a crash mid-write can't truncate the history.
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(tmp_path, path)
+    }
+
+    pub fn record(&mut self, id: &str, now: i64) {
+        let score = self.frecency(id, now) + 1.0;
+        self.entries.insert(
+            id.to_string(),
+            Usage {
+                score,
+                last_used: now,
+            },
+        );
+    }
+
+    pub fn forget(&mut self, id: &str) -> bool {
+        self.entries.remove(id).is_some()
+    }
+
+    // The decayed launch score at `now`; 0 for apps never launched.
+    pub fn frecency(&self, id: &str, now: i64) -> f64 {
+        let Some(usage) = self.entries.get(id) else {
+            return 0.0;
+        };
+        let age = (now - usage.last_used).max(0) as f64;
+        usage.score * 0.5f64.powf(age / HALF_LIFE_SECS)
+    }
+}
+
+pub fn record_launch(id: &str) {
+    let mut history = History::load();
+    history.record(id, now());
+    let _ = history.save();
+}
+
+// Removes `id` from the stored history; returns whether it was there.
+pub fn forget_launches(id: &str) -> io::Result<bool> {
+    let mut history = History::load();
+    let forgotten = history.forget(id);
+    if forgotten {
+        history.save()?;
+    }
+    Ok(forgotten)
+}
+
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+fn history_path() -> Option<PathBuf> {
+    dirs::state_dir().map(|dir| dir.join("bitpop").join("history"))
+}
@@ -1,18 +1,104 @@
-use gtk4::Image;
 use gtk4::gdk::Display;
+use gtk4::gdk_pixbuf::Pixbuf;
+use gtk4::prelude::*;
+use gtk4::{IconPaintable, Image, gdk, gio, glib};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+thread_local! {
+    // Thumbnails by file and size; None for files that couldn't be decoded
+    static THUMBNAILS: RefCell<HashMap<(PathBuf, i32), Option<gdk::Texture>>> =
+        RefCell::new(HashMap::new());
+}
 
 pub fn load_app_icon(icon_name: &str, size: i32) -> Image {
+    let image = Image::from_paintable(Some(&lookup_app_icon(icon_name, size)));
+    image.set_pixel_size(size);
+    image
+}
+
+// An empty image that looks its icon up once it is first mapped, in an idle
+// callback so the frame that shows it isn't held up by the icon theme. Images
+// that never get mapped, like those of collapsed rows, cost nothing.
+pub fn lazy_app_icon(icon_name: &str, size: i32) -> Image {
+    let image = Image::new();
+    image.set_pixel_size(size);
+
+    let icon_name = icon_name.to_string();
+    let requested = Cell::new(false);
+    image.connect_map(move |image| {
+        if requested.replace(true) {
+            return;
+        }
+        let image_weak = image.downgrade();
+        let icon_name = icon_name.clone();
+        glib::idle_add_local_once(move || {
+            if let Some(image) = image_weak.upgrade() {
+                image.set_paintable(Some(&lookup_app_icon(&icon_name, size)));
+            }
+        });
+    });
+
+    image
+}
+
+pub fn lookup_app_icon(icon_name: &str, size: i32) -> IconPaintable {
     let theme = gtk4::IconTheme::for_display(&Display::default().expect("No display"));
 
-    let icon = theme.lookup_icon(
+    theme.lookup_icon(
         icon_name,
         &[],
         size,
         1,
         gtk4::TextDirection::Ltr,
         gtk4::IconLookupFlags::empty(),
-    );
-    let image = Image::from_paintable(Some(&icon));
-    image.set_pixel_size(size);
-    image
+    )
+}
+
+// The theme's icon for the type of file `path` is, guessed from its name alone
+// so nothing is read from disk while results scroll by.
+pub fn lookup_file_icon(path: &Path, is_dir: bool, size: i32) -> IconPaintable {
+    if is_dir {
+        return lookup_app_icon("folder", size);
+    }
+
+    let (content_type, _) = gio::content_type_guess(Some(path), &[]);
+    let theme = gtk4::IconTheme::for_display(&Display::default().expect("No display"));
+    theme.lookup_by_gicon(
+        &gio::content_type_get_icon(&content_type),
+        size,
+        1,
+        gtk4::TextDirection::Ltr,
+        gtk4::IconLookupFlags::empty(),
+    )
+}
+
+// Hands `on_loaded` a thumbnail of the image at `path`, scaled to fit `size`.
+// Images are decoded on a GIO worker thread at that size, so a big screenshot
+// doesn't hold up scrolling, and kept for the next time. A cached thumbnail is
+// handed over right away; nothing is for files that aren't images.
+pub fn image_thumbnail(path: &Path, size: i32, on_loaded: impl FnOnce(gdk::Texture) + 'static) {
+    let key = (path.to_path_buf(), size);
+    if let Some(cached) = THUMBNAILS.with(|thumbnails| thumbnails.borrow().get(&key).cloned()) {
+        if let Some(texture) = cached {
+            on_loaded(texture);
+        }
+        return;
+    }
+
+    glib::spawn_future_local(async move {
+        let file = gio::File::for_path(&key.0);
+        let pixbuf = match file.read_future(glib::Priority::LOW).await {
+            Ok(stream) => Pixbuf::from_stream_at_scale_future(&stream, size, size, true)
+                .await
+                .ok(),
+            Err(_) => None,
+        };
+        let texture = pixbuf.map(|pixbuf| gdk::Texture::for_pixbuf(&pixbuf));
+        THUMBNAILS.with(|thumbnails| thumbnails.borrow_mut().insert(key, texture.clone()));
+        if let Some(texture) = texture {
+            on_loaded(texture);
+        }
+    });
 }
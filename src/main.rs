@@ -1,16 +1,46 @@
+mod app_index;
+mod app_watcher;
 mod apps;
+mod calc;
+mod clipboard;
+mod commands;
+mod config;
+mod convert;
+mod desktop_entry;
+mod exec;
+mod file_search;
+mod files;
+mod fuzzy;
+mod history;
 mod icons;
+mod pinned;
+mod plugins;
+mod providers;
+mod result_list;
+mod search;
 mod system;
+mod terminal;
 mod ui;
+mod windows;
 
 use gtk4::prelude::*;
 use gtk4::{Application, glib};
 
+use clipboard::record_selection;
+use history::forget_launches;
 use ui::{build_ui, check_existing_instance, remove_lock_file, setup_cleanup, write_lock_file};
 
-const APP_ID: &str = "com.bitpop.quickaccess";
+pub const APP_ID: &str = "com.bitpop.quickaccess";
 
 fn main() -> glib::ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("--forget") => return forget(args.get(2)),
+        Some("--clipboard-daemon") => return clipboard_daemon(),
+        Some("--clipboard-store") => return clipboard_store(),
+        _ => {}
+    }
+
     if let Some(pid) = check_existing_instance() {
         let _ = std::process::Command::new("kill")
             .args(&["-TERM", &pid.to_string()])
@@ -34,3 +64,61 @@ fn main() -> glib::ExitCode {
 
     exit_code
 }
+
+// `bitpop --forget <desktop-file-id>` drops an app from the launch history.
+fn forget(id: Option<&String>) -> glib::ExitCode {
+    let Some(id) = id else {
+        eprintln!("usage: bitpop --forget <desktop-file-id>");
+        return glib::ExitCode::FAILURE;
+    };
+
+    match forget_launches(id) {
+        Ok(true) => glib::ExitCode::SUCCESS,
+        Ok(false) => {
+            eprintln!("{} is not in the launch history", id);
+            glib::ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("Couldn't update launch history: {}", err);
+            glib::ExitCode::FAILURE
+        }
+    }
+}
+
+// `bitpop --clipboard-daemon` keeps the clipboard history, and is meant to be
+// started with the session. wl-paste watches the Wayland clipboard and hands
+// every new selection to `bitpop --clipboard-store` on stdin.
+fn clipboard_daemon() -> glib::ExitCode {
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(err) => {
+            eprintln!("Couldn't find the bitpop binary: {}", err);
+            return glib::ExitCode::FAILURE;
+        }
+    };
+
+    let status = std::process::Command::new("wl-paste")
+        .arg("--watch")
+        .arg(exe)
+        .arg("--clipboard-store")
+        .status();
+    match status {
+        Ok(status) if status.success() => glib::ExitCode::SUCCESS,
+        Ok(_) => glib::ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("Couldn't run wl-paste (from wl-clipboard): {}", err);
+            glib::ExitCode::FAILURE
+        }
+    }
+}
+
+fn clipboard_store() -> glib::ExitCode {
+    let state = std::env::var("CLIPBOARD_STATE").ok();
+    match record_selection(std::io::stdin(), state.as_deref()) {
+        Ok(()) => glib::ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Couldn't update the clipboard history: {}", err);
+            glib::ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use gtk4::prelude::*;
+use gtk4::{FlowBox, FlowBoxChild, gdk, glib};
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+use crate::apps::AppEntry;
+use crate::config::{Config, save_pinned};
+use crate::icons::lazy_app_icon;
+
+// Icon grid of pinned apps shown above the search entry. Tiles launch on
+// click/Enter, reorder by drag and drop or Ctrl+Left/Right, and are unpinned
+// with Delete or a right click. The order is saved to the config file.
+pub struct PinnedApps {
+    flow_box: FlowBox,
+    ids: RefCell<Vec<String>>,
+    shown: RefCell<Vec<AppEntry>>,
+    apps: Rc<RefCell<Vec<AppEntry>>>,
+    on_activate: Box<dyn Fn(&AppEntry)>,
+    on_error: Box<dyn Fn(&str)>,
+}
+
+impl PinnedApps {
+    // `on_error` reports pins that couldn't be saved.
+    pub fn new(
+        apps: Rc<RefCell<Vec<AppEntry>>>,
+        on_activate: impl Fn(&AppEntry) + 'static,
+        on_error: impl Fn(&str) + 'static,
+    ) -> Rc<PinnedApps> {
+        let flow_box = FlowBox::new();
+        flow_box.add_css_class("pinned-apps");
+        flow_box.set_selection_mode(gtk4::SelectionMode::None);
+        flow_box.set_homogeneous(true);
+        flow_box.set_max_children_per_line(8);
+        flow_box.set_min_children_per_line(8);
+        flow_box.set_activate_on_single_click(true);
+
+        let pinned = Rc::new(PinnedApps {
+            flow_box,
+            ids: RefCell::new(Config::load().pinned),
+            shown: RefCell::new(Vec::new()),
+            apps,
+            on_activate: Box::new(on_activate),
+            on_error: Box::new(on_error),
+        });
+
+        let pinned_weak = Rc::downgrade(&pinned);
+        pinned.flow_box.connect_child_activated(move |_, child| {
+            let Some(pinned) = pinned_weak.upgrade() else {
+                return;
+            };
+            let app = pinned.shown.borrow().get(child.index() as usize).cloned();
+            if let Some(app) = app {
+                (pinned.on_activate)(&app);
+            }
+        });
+
+        pinned.rebuild();
+        pinned
+    }
+
+    pub fn widget(&self) -> &FlowBox {
+        &self.flow_box
+    }
+
+    pub fn toggle(self: &Rc<Self>, id: &str) {
+        {
+            let mut ids = self.ids.borrow_mut();
+            if let Some(index) = ids.iter().position(|pinned| pinned == id) {
+                ids.remove(index);
+            } else {
+                ids.push(id.to_string());
+            }
+        }
+        self.save();
+        self.rebuild();
+    }
+
+    // Moves `id` to where the shown tile at `to` is. Pins whose apps aren't
+    // installed stay in the list but aren't shown, so positions are mapped
+    // through the shown tiles rather than used as indices into `ids` directly.
+    fn move_pin(self: &Rc<Self>, id: &str, to: usize) {
+        let Some(target_id) = self.shown.borrow().get(to).map(|app| app.id.clone()) else {
+            return;
+        };
+        if target_id == id {
+            return;
+        }
+
+        {
+            let mut ids = self.ids.borrow_mut();
+            let Some(from) = ids.iter().position(|pinned| pinned == id) else {
+                return;
+            };
+            let moved = ids.remove(from);
+            let Some(target) = ids.iter().position(|pinned| *pinned == target_id) else {
+                ids.insert(from, moved);
+                return;
+            };
+            let insert_at = if from <= target { target + 1 } else { target };
+            ids.insert(insert_at, moved);
+        }
+
+        self.save();
+        self.rebuild();
+        if let Some(child) = self.flow_box.child_at_index(to as i32) {
+            child.grab_focus();
+        }
+    }
+
+    fn save(&self) {
+        if let Err(err) = save_pinned(&self.ids.borrow()) {
+            (self.on_error)(&format!("Couldn't save the pinned apps: {}", err));
+        }
+    }
+
+    pub fn rebuild(self: &Rc<Self>) {
+        self.flow_box.remove_all();
+
+        let shown: Vec<AppEntry> = {
+            let apps = self.apps.borrow();
+            self.ids
+                .borrow()
+                .iter()
+                .filter_map(|id| apps.iter().find(|app| app.id == *id).cloned())
+                .collect()
+        };
+
+        for (index, app) in shown.iter().enumerate() {
+            let child = self.create_tile(app, index);
+            self.flow_box.insert(&child, -1);
+        }
+
+        self.flow_box.set_visible(!shown.is_empty());
+        *self.shown.borrow_mut() = shown;
+    }
+
+    fn create_tile(self: &Rc<Self>, app: &AppEntry, index: usize) -> FlowBoxChild {
+        let child = FlowBoxChild::new();
+        child.add_css_class("pinned-app");
+        child.set_tooltip_text(Some(&app.name));
+        child.set_child(Some(&lazy_app_icon(&app.icon, 32)));
+
+        // Drag and drop reordering
+        let drag_source = gtk4::DragSource::new();
+        drag_source.set_actions(gdk::DragAction::MOVE);
+        let id = app.id.clone();
+        drag_source
+            .connect_prepare(move |_, _, _| Some(gdk::ContentProvider::for_value(&id.to_value())));
+        child.add_controller(drag_source);
+
+        let drop_target = gtk4::DropTarget::new(glib::Type::STRING, gdk::DragAction::MOVE);
+        let pinned_weak = Rc::downgrade(self);
+        drop_target.connect_drop(move |_, value, _, _| {
+            let (Some(pinned), Ok(id)) = (pinned_weak.upgrade(), value.get::<String>()) else {
+                return false;
+            };
+            pinned.move_pin(&id, index);
+            true
+        });
+        child.add_controller(drop_target);
+
+        // Keyboard reordering and unpinning
+        let key_controller = gtk4::EventControllerKey::new();
+        let pinned_weak = Rc::downgrade(self);
+        let id = app.id.clone();
+        key_controller.connect_key_pressed(move |_, key, _, modifiers| {
+            let Some(pinned) = pinned_weak.upgrade() else {
+                return glib::Propagation::Proceed;
+            };
+            let ctrl = modifiers.contains(gdk::ModifierType::CONTROL_MASK);
+
+            if ctrl && key == gdk::Key::Left && index > 0 {
+                pinned.move_pin(&id, index - 1);
+                return glib::Propagation::Stop;
+            }
+            if ctrl && key == gdk::Key::Right {
+                pinned.move_pin(&id, index + 1);
+                return glib::Propagation::Stop;
+            }
+            if key == gdk::Key::Delete {
+                pinned.toggle(&id);
+                return glib::Propagation::Stop;
+            }
+
+            glib::Propagation::Proceed
+        });
+        child.add_controller(key_controller);
+
+        let id = app.id.clone();
+        child.add_controller(toggle_pin_on_right_click(Rc::downgrade(self), move || {
+            Some(id.clone())
+        }));
+
+        child
+    }
+}
+
+// Right click on a tile or on an app row in the results toggles whether the
+// app is pinned. Result rows are recycled, so the app is looked up on click.
+pub fn toggle_pin_on_right_click(
+    pinned: Weak<PinnedApps>,
+    id: impl Fn() -> Option<String> + 'static,
+) -> gtk4::GestureClick {
+    let gesture = gtk4::GestureClick::new();
+    gesture.set_button(gdk::BUTTON_SECONDARY);
+    gesture.connect_pressed(move |_, _, _, _| {
+        if let (Some(pinned), Some(id)) = (pinned.upgrade(), id()) {
+            pinned.toggle(&id);
+        }
+    });
+    gesture
+}
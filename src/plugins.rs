@@ -0,0 +1,537 @@
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::expand_home;
+use crate::desktop_entry::Group;
+use crate::exec;
+
+// How long a query waits for a plugin's results by default before they're
+// considered stale and dropped.
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(500);
+
+// A plugin isn't restarted more often than this after it exits, so one that
+// crashes on start doesn't get respawned on every keystroke.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+// How long a plugin gets to exit by itself after its stdin is closed before
+// it is killed.
+const EXIT_GRACE: Duration = Duration::from_millis(200);
+
+// A search provider run as a separate program, registered in the config file
+// with a group per plugin:
+//
+//   [Plugin tickets]
+//   Exec=ticket-search --cache ~/.cache/tickets
+//   Prefix=#
+//   Timeout=300
+//
+// Without a prefix its results are merged with the apps'. Timeout is in
+// milliseconds. A "~" at the start of an Exec argument is the home directory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PluginConfig {
+    pub name: String,
+    pub exec: String,
+    pub prefix: Option<String>,
+    pub timeout: Duration,
+}
+
+impl PluginConfig {
+    pub fn from_group(group: &Group) -> Option<PluginConfig> {
+        let name = group.name().strip_prefix("Plugin ")?.trim();
+        let exec = group.string("Exec").filter(|exec| !exec.is_empty())?;
+        if name.is_empty() {
+            return None;
+        }
+
+        Some(PluginConfig {
+            name: name.to_string(),
+            exec,
+            prefix: group.string("Prefix").filter(|prefix| !prefix.is_empty()),
+            timeout: group
+                .raw("Timeout")
+                .and_then(|ms| ms.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_TIMEOUT),
+        })
+    }
+}
+
+// Sent to the plugin's stdin, one JSON object per line:
+//
+//   {"type":"query","id":3,"text":"vpn"}
+//   {"type":"cancel","id":3}
+//   {"type":"activate","result":"T-1234"}
+//
+// A query supersedes the one before it, which is cancelled first; a plugin may
+// abort work on it, and results for it are ignored either way.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Request {
+    Query { id: u64, text: String },
+    Cancel { id: u64 },
+    Activate { result: String },
+}
+
+// Read from the plugin's stdout, one JSON object per line:
+//
+//   {"type":"results","id":3,"results":[{"id":"T-1234","title":"VPN drops"}]}
+//
+// Lines that aren't valid responses are skipped.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Response {
+    Results { id: u64, results: Vec<PluginResult> },
+}
+
+// A result row. Activating it sends `activate` with its ID and closes the
+// popup, unless it has `copy` text, which goes to the clipboard instead.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct PluginResult {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub subtitle: Option<String>,
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub score: i32,
+    #[serde(default)]
+    pub copy: Option<String>,
+}
+
+pub fn encode(request: &Request) -> String {
+    let mut line = serde_json::to_string(request).unwrap_or_default();
+    line.push('\n');
+    line
+}
+
+pub fn decode(line: &str) -> Option<Response> {
+    serde_json::from_str(line.trim()).ok()
+}
+
+// The queries sent to a plugin and the results it answered the latest one
+// with. Results for an earlier query, or that arrive after the timeout, are
+// dropped.
+pub struct Queries {
+    timeout: Duration,
+    id: u64,
+    text: String,
+    // When the current query was sent, until the plugin answers it
+    pending: Option<Instant>,
+    results: Vec<PluginResult>,
+    started: Option<Instant>,
+}
+
+impl Queries {
+    pub fn new(timeout: Duration) -> Queries {
+        Queries {
+            timeout,
+            id: 0,
+            text: String::new(),
+            pending: None,
+            results: Vec::new(),
+            started: None,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn results(&self) -> &[PluginResult] {
+        &self.results
+    }
+
+    // Replaces the current query with one for `text`, sent at `now`. Returns
+    // what to send the plugin: a cancel for the previous query if it is still
+    // unanswered, then the new query.
+    pub fn next(&mut self, text: &str, now: Instant) -> Vec<Request> {
+        let mut requests = Vec::new();
+        if self.pending.is_some() {
+            requests.push(Request::Cancel { id: self.id });
+        }
+        self.id += 1;
+        self.text = text.to_string();
+        self.pending = Some(now);
+        self.results.clear();
+        requests.push(Request::Query {
+            id: self.id,
+            text: text.to_string(),
+        });
+        requests
+    }
+
+    // Keeps the results if they answer the current query within the timeout.
+    pub fn accept(&mut self, response: Response, now: Instant) -> bool {
+        let Response::Results { id, results } = response;
+        let in_time = self
+            .pending
+            .is_some_and(|sent| now.saturating_duration_since(sent) <= self.timeout);
+        if id != self.id || !in_time {
+            return false;
+        }
+        self.pending = None;
+        self.results = results;
+        true
+    }
+
+    // Whether the plugin may be started at `now`; it is at most once per
+    // RESTART_DELAY.
+    pub fn may_start(&mut self, now: Instant) -> bool {
+        if self
+            .started
+            .is_some_and(|started| now.saturating_duration_since(started) < RESTART_DELAY)
+        {
+            return false;
+        }
+        self.started = Some(now);
+        true
+    }
+}
+
+fn plugin_argv(exec: &str) -> Option<Vec<String>> {
+    let argv = exec::tokenize(exec).ok().filter(|argv| !argv.is_empty())?;
+    Some(
+        argv.into_iter()
+            .map(|arg| match expand_home(&arg) {
+                Some(path) if arg.starts_with('~') => path.to_string_lossy().into_owned(),
+                _ => arg,
+            })
+            .collect(),
+    )
+}
+
+// A running plugin. Requests are written and responses read on threads of
+// their own, so a plugin that stops reading or answering can't block the
+// popup, and one that crashes only ends those threads.
+pub struct Plugin {
+    child: Child,
+    requests: mpsc::Sender<(Request, Option<mpsc::Sender<()>>)>,
+}
+
+impl Plugin {
+    // Starts the plugin in the home directory; `on_response` is called on the
+    // reader thread for every response until its stdout closes.
+    pub fn spawn(
+        config: &PluginConfig,
+        on_response: impl Fn(Response) + Send + 'static,
+    ) -> Result<Plugin, String> {
+        let argv = plugin_argv(&config.exec)
+            .ok_or_else(|| format!("Plugin {} has an invalid Exec", config.name))?;
+
+        let mut command = Command::new(&argv[0]);
+        command
+            .args(&argv[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped());
+        if let Some(home) = dirs::home_dir() {
+            command.current_dir(home);
+        }
+        let mut child = command
+            .spawn()
+            .map_err(|err| format!("Couldn't start plugin {}: {}", config.name, err))?;
+
+        let (Some(mut stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(format!("Couldn't start plugin {}", config.name));
+        };
+
+        let (requests, pending) = mpsc::channel::<(Request, Option<mpsc::Sender<()>>)>();
+        thread::spawn(move || {
+            for (request, written) in pending {
+                if stdin.write_all(encode(&request).as_bytes()).is_err() || stdin.flush().is_err() {
+                    break;
+                }
+                if let Some(written) = written {
+                    let _ = written.send(());
+                }
+            }
+        });
+
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if let Some(response) = decode(&line) {
+                    on_response(response);
+                }
+            }
+        });
+
+        Ok(Plugin { child, requests })
+    }
+
+    pub fn send(&self, request: Request) -> bool {
+        self.requests.send((request, None)).is_ok()
+    }
+
+    // Like `send`, but waits up to `timeout` for the request to be written, for
+    // requests sent just before the popup closes.
+    pub fn send_and_wait(&self, request: Request, timeout: Duration) -> bool {
+        let (written, done) = mpsc::channel();
+        self.requests.send((request, Some(written))).is_ok() && done.recv_timeout(timeout).is_ok()
+    }
+
+    pub fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+// Closing stdin is how a plugin learns to exit: dropping the requests ends the
+// writer thread, which closes it. The kill is for plugins that don't exit
+// within EXIT_GRACE.
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        drop(std::mem::replace(&mut self.requests, mpsc::channel().0));
+        let closed = Instant::now();
+        while self.is_running() && closed.elapsed() < EXIT_GRACE {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::desktop_entry::KeyFile;
+    use std::env;
+    use std::fs;
+
+    fn config(exec: &str) -> PluginConfig {
+        PluginConfig {
+            name: "test".to_string(),
+            exec: exec.to_string(),
+            prefix: None,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    #[test]
+    fn plugins_are_read_from_their_config_groups() {
+        let file = KeyFile::parse(
+            "[General]\nTerminal=foot\n\n\
+             [Plugin tickets]\nExec=ticket-search --cache x\nPrefix=#\nTimeout=300\n\n\
+             [Plugin snippets]\nExec=snippets\n\n\
+             [Plugin broken]\nPrefix=?\n",
+        );
+        let plugins: Vec<PluginConfig> =
+            file.groups().filter_map(PluginConfig::from_group).collect();
+
+        assert_eq!(
+            plugins,
+            vec![
+                PluginConfig {
+                    name: "tickets".to_string(),
+                    exec: "ticket-search --cache x".to_string(),
+                    prefix: Some("#".to_string()),
+                    timeout: Duration::from_millis(300),
+                },
+                PluginConfig {
+                    name: "snippets".to_string(),
+                    exec: "snippets".to_string(),
+                    prefix: None,
+                    timeout: DEFAULT_TIMEOUT,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn requests_and_responses_are_json_lines() {
+        assert_eq!(
+            encode(&Request::Query {
+                id: 3,
+                text: "vpn".to_string()
+            }),
+            "{\"type\":\"query\",\"id\":3,\"text\":\"vpn\"}\n"
+        );
+        assert_eq!(
+            encode(&Request::Activate {
+                result: "T-1".to_string()
+            }),
+            "{\"type\":\"activate\",\"result\":\"T-1\"}\n"
+        );
+
+        let response = decode(concat!(
+            r#"{"type":"results","id":3,"results":["#,
+            r#"{"id":"T-1","title":"VPN drops","score":5},"#,
+            r#"{"id":"s","title":"Sig","copy":"-- me"}]}"#,
+        ));
+        let Some(Response::Results { id, results }) = response else {
+            panic!("not decoded");
+        };
+        assert_eq!(id, 3);
+        assert_eq!(results[0].score, 5);
+        assert_eq!(results[0].subtitle, None);
+        assert_eq!(results[1].copy.as_deref(), Some("-- me"));
+
+        assert_eq!(decode("not json"), None);
+        assert_eq!(decode(r#"{"type":"unknown"}"#), None);
+    }
+
+    fn results(id: u64, title: &str) -> Response {
+        Response::Results {
+            id,
+            results: vec![PluginResult {
+                id: "1".to_string(),
+                title: title.to_string(),
+                subtitle: None,
+                icon: None,
+                score: 0,
+                copy: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn a_new_query_cancels_the_unanswered_one() {
+        let now = Instant::now();
+        let mut queries = Queries::new(DEFAULT_TIMEOUT);
+
+        assert_eq!(
+            queries.next("v", now),
+            vec![Request::Query {
+                id: 1,
+                text: "v".to_string()
+            }]
+        );
+        assert_eq!(
+            queries.next("vp", now),
+            vec![
+                Request::Cancel { id: 1 },
+                Request::Query {
+                    id: 2,
+                    text: "vp".to_string()
+                }
+            ]
+        );
+
+        assert!(queries.accept(results(2, "VPN"), now));
+        assert_eq!(queries.next("vpn", now).len(), 1);
+        assert_eq!(queries.text(), "vpn");
+    }
+
+    #[test]
+    fn results_for_earlier_queries_are_dropped() {
+        let now = Instant::now();
+        let mut queries = Queries::new(DEFAULT_TIMEOUT);
+        queries.next("v", now);
+        queries.next("vp", now);
+
+        assert!(!queries.accept(results(1, "stale"), now));
+        assert!(queries.results().is_empty());
+        assert!(queries.accept(results(2, "VPN"), now));
+        assert_eq!(queries.results()[0].title, "VPN");
+
+        // Answered already
+        assert!(!queries.accept(results(2, "again"), now));
+        assert_eq!(queries.results()[0].title, "VPN");
+
+        queries.next("vpn", now);
+        assert!(queries.results().is_empty());
+    }
+
+    #[test]
+    fn results_after_the_timeout_are_dropped() {
+        let sent = Instant::now();
+        let mut queries = Queries::new(Duration::from_millis(300));
+
+        queries.next("v", sent);
+        assert!(!queries.accept(results(1, "late"), sent + Duration::from_millis(301)));
+        assert!(queries.results().is_empty());
+
+        queries.next("vp", sent);
+        assert!(queries.accept(results(2, "VPN"), sent + Duration::from_millis(300)));
+    }
+
+    #[test]
+    fn restarts_are_throttled() {
+        let now = Instant::now();
+        let mut queries = Queries::new(DEFAULT_TIMEOUT);
+
+        assert!(queries.may_start(now));
+        assert!(!queries.may_start(now + Duration::from_secs(1)));
+        assert!(!queries.may_start(now + RESTART_DELAY - Duration::from_millis(1)));
+        assert!(queries.may_start(now + RESTART_DELAY));
+        assert!(!queries.may_start(now + RESTART_DELAY));
+    }
+
+    #[test]
+    fn a_tilde_in_exec_is_the_home_directory() {
+        let home = dirs::home_dir().unwrap();
+        let cache = home.join(".cache/tickets").to_string_lossy().into_owned();
+
+        assert_eq!(
+            plugin_argv("ticket-search --cache ~/.cache/tickets ~user a~b"),
+            Some(vec![
+                "ticket-search".to_string(),
+                "--cache".to_string(),
+                cache,
+                "~user".to_string(),
+                "a~b".to_string(),
+            ])
+        );
+        assert_eq!(plugin_argv(""), None);
+    }
+
+    #[test]
+    fn a_plugin_answers_queries_over_its_pipes() {
+        // Answers every query with one result titled after the query text
+        let script = r#"
+            format='{"type":"results","id":%s,"results":[{"id":"1","title":"%s"}]}\n'
+            while read -r line; do
+                case "$line" in *'"query"'*)
+                    id=${line#*\"id\":}; id=${id%%,*}
+                    text=${line#*\"text\":\"}; text=${text%%\"*}
+                    printf "$format" "$id" "$text"
+                esac
+            done"#;
+        let path = env::temp_dir().join(format!("bitpop-plugin-{}.sh", std::process::id()));
+        fs::write(&path, script).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let exec = format!("sh {}", path.display());
+        let mut plugin = Plugin::spawn(&config(&exec), move |response| {
+            let _ = sender.send(response);
+        })
+        .unwrap();
+
+        assert!(plugin.send(Request::Query {
+            id: 1,
+            text: "vpn".to_string()
+        }));
+        let Response::Results { id, results } =
+            receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(results[0].title, "vpn");
+        assert!(plugin.is_running());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_dropped_plugin_may_exit_by_itself() {
+        let marker = env::temp_dir().join(format!("bitpop-plugin-{}.exited", std::process::id()));
+        let exec = format!("sh -c \"cat >/dev/null; touch {}\"", marker.display());
+        drop(Plugin::spawn(&config(&exec), |_| {}).unwrap());
+
+        assert!(marker.exists());
+        fs::remove_file(marker).unwrap();
+    }
+
+    #[test]
+    fn a_crashed_plugin_is_noticed() {
+        let mut plugin = Plugin::spawn(&config("sh -c \"exit 3\""), |_| {}).unwrap();
+        let _ = plugin.child.wait();
+        assert!(!plugin.is_running());
+
+        assert!(Plugin::spawn(&config("/nonexistent/plugin"), |_| {}).is_err());
+        assert!(Plugin::spawn(&config(""), |_| {}).is_err());
+    }
+}
@@ -0,0 +1,690 @@
+use gtk4::glib;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::apps::{
+    AppEntry, compare_results, launch_action, launch_app, search_path, search_score,
+};
+use crate::calc;
+use crate::clipboard::{self, ClipContent, ClipEntry, ClipboardStore};
+use crate::commands::{self, CommandHistory, Completion, CompletionKind};
+use crate::config::Config;
+use crate::convert;
+use crate::file_search;
+use crate::files::{self, FileEntry, FileIndex, display_path};
+use crate::history::{self, History};
+use crate::plugins::{Plugin, PluginConfig, PluginResult, Queries, Request, Response};
+use crate::search::{
+    ANSWER_SCORE, Action, Modifier, Outcome, ResultIcon, SearchProvider, SearchResult,
+};
+use crate::windows::{self, Compositor, Window};
+
+// Installed apps, ranked by how well they match and how often and recently
+// they were launched. The list is filled in by the AppWatcher, and the result
+// list keeps it in a filtered, sorted model that asks here for the scores
+// rather than rebuilding every row for each search.
+pub struct AppProvider {
+    apps: RefCell<Vec<Rc<AppEntry>>>,
+    history: Rc<RefCell<History>>,
+    scores: RefCell<AppScores>,
+}
+
+// Scores for the current query, so sorting doesn't recompute them for every
+// comparison. Cleared when the query changes or the history does.
+#[derive(Default)]
+struct AppScores {
+    query: String,
+    now: i64,
+    by_id: HashMap<String, Option<i32>>,
+}
+
+impl AppProvider {
+    pub fn new() -> Rc<AppProvider> {
+        Rc::new(AppProvider {
+            apps: RefCell::new(Vec::new()),
+            history: Rc::new(RefCell::new(History::load())),
+            scores: RefCell::new(AppScores {
+                now: history::now(),
+                ..AppScores::default()
+            }),
+        })
+    }
+
+    pub fn set_apps(&self, apps: &[AppEntry]) {
+        *self.apps.borrow_mut() = apps.iter().cloned().map(Rc::new).collect();
+    }
+
+    pub fn apps(&self) -> Vec<Rc<AppEntry>> {
+        self.apps.borrow().clone()
+    }
+
+    pub fn app_of(&self, window: &Window) -> Option<Rc<AppEntry>> {
+        self.apps
+            .borrow()
+            .iter()
+            .find(|app| app.owns_window(&window.app_id))
+            .cloned()
+    }
+
+    pub fn set_query(&self, text: &str) {
+        let query = text.to_lowercase();
+        let mut scores = self.scores.borrow_mut();
+        if scores.query != query {
+            *scores = AppScores {
+                query,
+                now: history::now(),
+                by_id: HashMap::new(),
+            };
+        }
+    }
+
+    // Forgets the cached scores, after the launch history changed.
+    pub fn rescore(&self) {
+        let mut scores = self.scores.borrow_mut();
+        scores.now = history::now();
+        scores.by_id.clear();
+    }
+
+    // How well `app` matches the query, or None if it doesn't.
+    pub fn score(&self, app: &AppEntry) -> Option<i32> {
+        let mut scores = self.scores.borrow_mut();
+        if let Some(score) = scores.by_id.get(&app.id) {
+            return *score;
+        }
+        let score = search_score(app, &scores.query, &self.history.borrow(), scores.now);
+        scores.by_id.insert(app.id.clone(), score);
+        score
+    }
+
+    pub fn compare(&self, a: &AppEntry, b: &AppEntry) -> Ordering {
+        let (a_score, b_score) = (self.score(a), self.score(b));
+        compare_results((a_score.unwrap_or(0), a), (b_score.unwrap_or(0), b))
+    }
+
+    pub fn result(&self, app: &Rc<AppEntry>) -> SearchResult {
+        app_result(app, self.score(app).unwrap_or(0), &self.history)
+    }
+}
+
+fn app_result(app: &Rc<AppEntry>, score: i32, history: &Rc<RefCell<History>>) -> SearchResult {
+    let launched = app.clone();
+    let mut result = SearchResult::new(
+        &app.name,
+        ResultIcon::Named(app.icon.clone()),
+        Action::new("Launch", move || {
+            windows::focus_or_launch(&launched).map(|()| Outcome::Done)
+        }),
+    );
+    result.score = score;
+    result.pin_id = Some(app.id.clone());
+
+    let launched = app.clone();
+    let mut new_instance = Action::new("New Window", move || {
+        launch_app(&launched).map(|()| Outcome::Done)
+    });
+    new_instance.icon = Some("window-new".to_string());
+    new_instance.modifier = Some(Modifier::Shift);
+    result.actions.push(new_instance);
+
+    // Desktop actions (jump list), e.g. "New Private Window"
+    for action in &app.actions {
+        let (launched, launched_action) = (app.clone(), action.clone());
+        let mut item = Action::new(&action.name, move || {
+            launch_action(&launched, &launched_action).map(|()| Outcome::Done)
+        });
+        item.icon = Some(action.icon.clone().unwrap_or_else(|| app.icon.clone()));
+        result.actions.push(item);
+    }
+
+    let (history, id) = (history.clone(), app.id.clone());
+    result.forget = Some(Rc::new(move || {
+        let mut history = history.borrow_mut();
+        if history.forget(&id) {
+            let _ = history.save();
+        }
+    }));
+
+    result
+}
+
+// The value of an arithmetic query, copied to the clipboard on Enter.
+pub struct CalculatorProvider;
+
+impl SearchProvider for CalculatorProvider {
+    fn search(&self, text: &str) -> Vec<SearchResult> {
+        let query = text.to_lowercase();
+        calc::answer(&query)
+            .map(|value| answer_result(value, query.trim()))
+            .into_iter()
+            .collect()
+    }
+}
+
+// Unit and timestamp conversions. Unit names are case-sensitive (Mb vs MB), so
+// unlike the calculator this sees the text as typed.
+pub struct ConversionProvider;
+
+impl SearchProvider for ConversionProvider {
+    fn search(&self, text: &str) -> Vec<SearchResult> {
+        convert::answer(text.trim())
+            .map(|value| answer_result(value, text.trim()))
+            .into_iter()
+            .collect()
+    }
+}
+
+fn answer_result(value: String, question: &str) -> SearchResult {
+    let copied = value.clone();
+    let mut result = SearchResult::new(
+        &value,
+        ResultIcon::Named("accessories-calculator".to_string()),
+        Action::new("Copy", move || Ok(Outcome::Copy(copied.clone()))),
+    );
+    result.subtitle = Some(format!("{} =", question));
+    result.score = ANSWER_SCORE;
+    result
+}
+
+// Shell command lines after a ">" or "!", completed from the command history
+// and the executables in $PATH. The executables are listed on a worker thread
+// the first time a command line is typed; `on_executables` is called when they
+// arrive so they can be added to the completions.
+pub struct CommandProvider {
+    history: Rc<RefCell<CommandHistory>>,
+    executables: Rc<RefCell<Vec<String>>>,
+    listing: Cell<bool>,
+    on_executables: Rc<dyn Fn()>,
+}
+
+impl CommandProvider {
+    pub fn new(on_executables: impl Fn() + 'static) -> Rc<CommandProvider> {
+        Rc::new(CommandProvider {
+            history: Rc::new(RefCell::new(CommandHistory::load())),
+            executables: Rc::new(RefCell::new(Vec::new())),
+            listing: Cell::new(false),
+            on_executables: Rc::new(on_executables),
+        })
+    }
+
+    fn list_executables(&self) {
+        let (sender, receiver) = async_channel::bounded(1);
+        thread::spawn(move || {
+            let _ = sender.send_blocking(commands::path_executables(&search_path()));
+        });
+
+        let (executables, on_executables) = (self.executables.clone(), self.on_executables.clone());
+        glib::spawn_future_local(async move {
+            if let Ok(listed) = receiver.recv().await {
+                *executables.borrow_mut() = listed;
+                on_executables();
+            }
+        });
+    }
+}
+
+impl SearchProvider for CommandProvider {
+    fn prefixes(&self) -> Vec<&str> {
+        commands::PREFIXES.to_vec()
+    }
+
+    fn search(&self, text: &str) -> Vec<SearchResult> {
+        let Some(line) = commands::command_line(text) else {
+            return Vec::new();
+        };
+        if !self.listing.replace(true) {
+            self.list_executables();
+        }
+
+        let completions =
+            commands::completions(line, &self.history.borrow(), &self.executables.borrow());
+        completions
+            .into_iter()
+            .map(|completion| command_result(text, completion, &self.history))
+            .collect()
+    }
+}
+
+fn command_result(
+    text: &str,
+    completion: Completion,
+    history: &Rc<RefCell<CommandHistory>>,
+) -> SearchResult {
+    let line = completion.line.clone();
+    let mut result = SearchResult::new(
+        &completion.line,
+        ResultIcon::Named("utilities-terminal".to_string()),
+        Action::new("Run", move || {
+            commands::run(&line, false).map(|()| Outcome::Done)
+        }),
+    );
+    result.subtitle = Some(
+        match completion.kind {
+            CompletionKind::Typed => "Run command, Shift+Enter to run it in a terminal",
+            CompletionKind::History => "Recent command",
+            CompletionKind::Executable => "Program",
+        }
+        .to_string(),
+    );
+    result.completion = commands::complete(text, &completion.line);
+
+    let line = completion.line.clone();
+    let mut in_terminal = Action::new("Run in Terminal", move || {
+        commands::run(&line, true).map(|()| Outcome::Done)
+    });
+    in_terminal.icon = Some("utilities-terminal".to_string());
+    in_terminal.modifier = Some(Modifier::Shift);
+    result.actions.push(in_terminal);
+
+    if completion.kind == CompletionKind::History {
+        let (history, line) = (history.clone(), completion.line);
+        result.forget = Some(Rc::new(move || {
+            let mut history = history.borrow_mut();
+            if history.forget(&line) {
+                let _ = history.save();
+            }
+        }));
+    }
+
+    result
+}
+
+// Files and folders under the configured roots after a "/" or "~". The index
+// is loaded in the background on the first file search; `on_index` is called
+// whenever it arrives so results typed in the meantime can be filled in.
+pub struct FileProvider {
+    index: Rc<RefCell<Option<FileIndex>>>,
+    indexing: Cell<bool>,
+    on_index: Rc<dyn Fn()>,
+}
+
+impl FileProvider {
+    pub fn new(on_index: impl Fn() + 'static) -> Rc<FileProvider> {
+        Rc::new(FileProvider {
+            index: Rc::new(RefCell::new(None)),
+            indexing: Cell::new(false),
+            on_index: Rc::new(on_index),
+        })
+    }
+}
+
+impl SearchProvider for FileProvider {
+    fn prefixes(&self) -> Vec<&str> {
+        files::PREFIXES.to_vec()
+    }
+
+    fn search(&self, text: &str) -> Vec<SearchResult> {
+        let Some(query) = files::file_query(text) else {
+            return Vec::new();
+        };
+
+        if !self.indexing.replace(true) {
+            let (index, on_index) = (self.index.clone(), self.on_index.clone());
+            file_search::start_indexing(move |new_index| {
+                *index.borrow_mut() = Some(new_index);
+                on_index();
+            });
+        }
+
+        let home = dirs::home_dir();
+        self.index
+            .borrow()
+            .as_ref()
+            .map(|index| index.search(query))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|file| file_result(file, home.as_deref()))
+            .collect()
+    }
+}
+
+fn file_result(file: FileEntry, home: Option<&std::path::Path>) -> SearchResult {
+    let opened = file.path.clone();
+    let mut result = SearchResult::new(
+        &file.name(),
+        ResultIcon::File {
+            path: file.path.clone(),
+            is_dir: file.is_dir,
+        },
+        Action::new("Open", move || files::open(&opened).map(|()| Outcome::Done)),
+    );
+    result.subtitle = Some(display_path(file.path.parent().unwrap_or(&file.path), home));
+
+    let revealed = file.path;
+    let mut reveal = Action::new("Show in Folder", move || {
+        file_search::reveal(&revealed);
+        Ok(Outcome::Done)
+    });
+    reveal.icon = Some("folder-open".to_string());
+    reveal.modifier = Some(Modifier::Control);
+    result.actions.push(reveal);
+
+    result
+}
+
+// How long activating a plugin result waits for the request to reach the
+// plugin before the popup closes.
+const PLUGIN_ACTIVATE_TIMEOUT: Duration = Duration::from_millis(200);
+
+// A plugin from the config file (see plugins.rs), started on its first search.
+// Results arrive asynchronously: each search sends a query and shows what the
+// plugin answered to it so far, and `on_results` is called when an answer
+// arrives in time so the list can be searched again.
+pub struct PluginProvider {
+    config: PluginConfig,
+    this: Weak<PluginProvider>,
+    plugin: RefCell<Option<Plugin>>,
+    queries: RefCell<Queries>,
+    responses: async_channel::Sender<Response>,
+}
+
+impl PluginProvider {
+    pub fn new(config: PluginConfig, on_results: impl Fn() + 'static) -> Rc<PluginProvider> {
+        let (sender, receiver) = async_channel::unbounded();
+        let provider = Rc::new_cyclic(|this| PluginProvider {
+            queries: RefCell::new(Queries::new(config.timeout)),
+            config,
+            this: this.clone(),
+            plugin: RefCell::new(None),
+            responses: sender,
+        });
+
+        let provider_weak = Rc::downgrade(&provider);
+        glib::spawn_future_local(async move {
+            while let Ok(response) = receiver.recv().await {
+                let Some(provider) = provider_weak.upgrade() else {
+                    break;
+                };
+                if provider
+                    .queries
+                    .borrow_mut()
+                    .accept(response, Instant::now())
+                {
+                    on_results();
+                }
+            }
+        });
+
+        provider
+    }
+
+    fn send_query(&self, text: &str) {
+        let now = Instant::now();
+        let requests = self.queries.borrow_mut().next(text, now);
+
+        let mut plugin = self.plugin.borrow_mut();
+        if !plugin.as_mut().is_some_and(|plugin| plugin.is_running()) {
+            *plugin = None;
+            if self.queries.borrow_mut().may_start(now) {
+                *plugin = self.start();
+            }
+        }
+        if let Some(plugin) = plugin.as_ref() {
+            for request in requests {
+                plugin.send(request);
+            }
+        }
+    }
+
+    fn start(&self) -> Option<Plugin> {
+        let sender = self.responses.clone();
+        Plugin::spawn(&self.config, move |response| {
+            let _ = sender.send_blocking(response);
+        })
+        .inspect_err(|err| eprintln!("{}", err))
+        .ok()
+    }
+
+    fn activate(&self, id: &str) -> Result<Outcome, String> {
+        let request = Request::Activate {
+            result: id.to_string(),
+        };
+        let sent = self
+            .plugin
+            .borrow()
+            .as_ref()
+            .is_some_and(|plugin| plugin.send_and_wait(request, PLUGIN_ACTIVATE_TIMEOUT));
+        if sent {
+            Ok(Outcome::Done)
+        } else {
+            Err(format!("Plugin {} isn't running", self.config.name))
+        }
+    }
+}
+
+impl SearchProvider for PluginProvider {
+    fn prefixes(&self) -> Vec<&str> {
+        self.config.prefix.as_deref().into_iter().collect()
+    }
+
+    fn search(&self, text: &str) -> Vec<SearchResult> {
+        let text = text.trim_start();
+        let query = match &self.config.prefix {
+            Some(prefix) => text.strip_prefix(prefix.as_str()).unwrap_or(text),
+            None => text,
+        }
+        .trim();
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        if query != self.queries.borrow().text() {
+            self.send_query(query);
+        }
+        self.queries
+            .borrow()
+            .results()
+            .iter()
+            .map(|result| plugin_result(result, &self.this))
+            .collect()
+    }
+}
+
+fn plugin_result(result: &PluginResult, provider: &Weak<PluginProvider>) -> SearchResult {
+    let activate = match &result.copy {
+        Some(copy) => {
+            let copied = copy.clone();
+            Action::new("Copy", move || Ok(Outcome::Copy(copied.clone())))
+        }
+        None => {
+            let (provider, id) = (provider.clone(), result.id.clone());
+            Action::new("Open", move || match provider.upgrade() {
+                Some(provider) => provider.activate(&id),
+                None => Err("The plugin was closed".to_string()),
+            })
+        }
+    };
+
+    let icon = result.icon.as_deref().unwrap_or("system-search");
+    let mut search_result =
+        SearchResult::new(&result.title, ResultIcon::Named(icon.to_string()), activate);
+    search_result.subtitle = result.subtitle.clone();
+    // Answers like the calculator's stay on top
+    search_result.score = result.score.min(ANSWER_SCORE - 1);
+    search_result
+}
+
+// How long a listing of the open windows is reused for the next keystrokes of
+// a window search before the compositor is asked again.
+const WINDOW_LIST_MAX_AGE: Duration = Duration::from_secs(2);
+
+// Open windows after an "@", focused on Enter. Windows open and close on other
+// outputs while the popup is up, so the listing is only reused for a moment,
+// and one that failed isn't reused at all.
+pub struct WindowProvider {
+    apps: Rc<AppProvider>,
+    listed: RefCell<Option<(Instant, Compositor, Vec<Window>)>>,
+}
+
+impl WindowProvider {
+    pub fn new(apps: Rc<AppProvider>) -> Rc<WindowProvider> {
+        Rc::new(WindowProvider {
+            apps,
+            listed: RefCell::new(None),
+        })
+    }
+
+    fn list(&self) -> Option<(Instant, Compositor, Vec<Window>)> {
+        let compositor = Compositor::detect()?;
+        let windows = compositor
+            .windows()
+            .inspect_err(|err| eprintln!("{}", err))
+            .ok()?;
+        Some((Instant::now(), compositor, windows))
+    }
+}
+
+impl SearchProvider for WindowProvider {
+    fn prefixes(&self) -> Vec<&str> {
+        windows::PREFIXES.to_vec()
+    }
+
+    fn search(&self, text: &str) -> Vec<SearchResult> {
+        let Some(query) = windows::window_query(text) else {
+            return Vec::new();
+        };
+        let mut listed = self.listed.borrow_mut();
+        let fresh = listed
+            .as_ref()
+            .is_some_and(|(when, _, _)| when.elapsed() < WINDOW_LIST_MAX_AGE);
+        if !fresh {
+            *listed = self.list();
+        }
+        let Some((_, compositor, windows)) = listed.as_ref() else {
+            return Vec::new();
+        };
+
+        windows::search(windows, query)
+            .into_iter()
+            .map(|(score, window)| {
+                let app = self.apps.app_of(window);
+                window_result(compositor, window, app.as_deref(), score)
+            })
+            .collect()
+    }
+}
+
+fn window_result(
+    compositor: &Compositor,
+    window: &Window,
+    app: Option<&AppEntry>,
+    score: i32,
+) -> SearchResult {
+    let (compositor, focused) = (compositor.clone(), window.clone());
+    let icon = app.map_or("window", |app| app.icon.as_str());
+    let mut result = SearchResult::new(
+        &window.title,
+        ResultIcon::Named(icon.to_string()),
+        Action::new("Focus", move || {
+            compositor.focus(&focused).map(|()| Outcome::Done)
+        }),
+    );
+
+    let app_name = app.map_or(window.app_id.as_str(), |app| app.name.as_str());
+    result.subtitle = Some(match &window.workspace {
+        Some(workspace) => format!("{} on workspace {}", app_name, workspace),
+        None => app_name.to_string(),
+    });
+    result.score = score;
+    result
+}
+
+// The clipboard history after a '"', recorded by `bitpop --clipboard-daemon`.
+// Enter copies an entry again, Ctrl+P pins it and Shift+Delete deletes it.
+pub struct ClipboardProvider {
+    // Loaded on the first clipboard search, and again after every change
+    store: Rc<RefCell<Option<ClipboardStore>>>,
+}
+
+impl ClipboardProvider {
+    pub fn new() -> Rc<ClipboardProvider> {
+        Rc::new(ClipboardProvider {
+            store: Rc::new(RefCell::new(None)),
+        })
+    }
+}
+
+impl SearchProvider for ClipboardProvider {
+    fn prefixes(&self) -> Vec<&str> {
+        clipboard::PREFIXES.to_vec()
+    }
+
+    fn search(&self, text: &str) -> Vec<SearchResult> {
+        let text = text.trim_start();
+        let Some(query) = clipboard::PREFIXES
+            .iter()
+            .find_map(|prefix| text.strip_prefix(prefix))
+        else {
+            return Vec::new();
+        };
+
+        let now = history::now();
+        let mut store = self.store.borrow_mut();
+        let store = store.get_or_insert_with(|| {
+            let max_age = Config::load().clipboard_max_age;
+            let _ = ClipboardStore::update(|store| store.prune(now, max_age));
+            ClipboardStore::load()
+        });
+
+        store
+            .search(query.trim())
+            .into_iter()
+            .map(|entry| clip_result(store, entry, now, &self.store))
+            .collect()
+    }
+}
+
+fn clip_result(
+    store: &ClipboardStore,
+    entry: &ClipEntry,
+    now: i64,
+    cache: &Rc<RefCell<Option<ClipboardStore>>>,
+) -> SearchResult {
+    let mut details = vec![clipboard::ago(entry.copied, now)];
+    if entry.pinned {
+        details.insert(0, "Pinned".to_string());
+    }
+
+    let mut result = match &entry.content {
+        ClipContent::Text { text } => {
+            let lines = text.lines().count();
+            if lines > 1 {
+                details.push(format!("{} lines", lines));
+            }
+            let copied = text.clone();
+            SearchResult::new(
+                &clipboard::preview(text),
+                ResultIcon::Named("edit-paste".to_string()),
+                Action::new("Copy", move || Ok(Outcome::Copy(copied.clone()))),
+            )
+        }
+        ClipContent::Image {
+            extension, size, ..
+        } => {
+            details.push(format!("{} KB", size.div_ceil(1024)));
+            let path = store.image_path(entry).unwrap_or_default();
+            let copied = path.clone();
+            SearchResult::new(
+                &format!("Image ({})", extension.to_uppercase()),
+                ResultIcon::Image(path),
+                Action::new("Copy", move || Ok(Outcome::CopyImage(copied.clone()))),
+            )
+        }
+    };
+    result.subtitle = Some(details.join(" · "));
+
+    let change = |change: fn(&mut ClipboardStore, u64) -> bool| -> Rc<dyn Fn()> {
+        let (cache, id) = (cache.clone(), entry.id);
+        Rc::new(move || {
+            if ClipboardStore::update(|store| change(store, id)).is_ok() {
+                *cache.borrow_mut() = Some(ClipboardStore::load());
+            }
+        })
+    };
+    result.toggle_pin = Some(change(ClipboardStore::toggle_pin));
+    result.forget = Some(change(ClipboardStore::delete));
+    result
+}
@@ -0,0 +1,489 @@
+use gtk4::prelude::*;
+use gtk4::{
+    Box as GtkBox, Button, CustomFilter, CustomSorter, FilterChange, FilterListModel, Image, Label,
+    ListItem, ListScrollFlags, ListView, Orientation, Revealer, SignalListItemFactory,
+    SingleSelection, SortListModel, SorterChange, ToggleButton, gdk, gio, glib,
+};
+use std::cell::{Cell, RefCell};
+use std::cmp::{Ordering, Reverse};
+use std::rc::{Rc, Weak};
+
+use crate::apps::AppEntry;
+use crate::icons::{image_thumbnail, lazy_app_icon, lookup_app_icon, lookup_file_icon};
+use crate::pinned::{PinnedApps, toggle_pin_on_right_click};
+use crate::providers::AppProvider;
+use crate::search::{Action, ResultIcon, SearchResult};
+
+type OnAction = Rc<dyn Fn(&Action)>;
+
+// The results of the current search, the installed apps mixed in with what
+// the other providers found by score. The apps stay in the store for good,
+// behind a filter and a sorter, so a keystroke only re-checks them instead of
+// building a row for every app; the providers' few results are swapped in
+// after them and sorted into place. Rows are recycled by the ListView, so only
+// the visible ones exist and their icons are looked up as they scroll into
+// view.
+pub struct ResultList {
+    app_provider: Rc<AppProvider>,
+    // The apps, then the other providers' results as ProviderRows
+    store: gio::ListStore,
+    app_count: Cell<u32>,
+    apps_hidden: Rc<Cell<bool>>,
+    app_query: RefCell<String>,
+    filter: CustomFilter,
+    sorter: CustomSorter,
+    selection: SingleSelection,
+    list_view: ListView,
+}
+
+// A result from a provider other than the apps, with its place in the order
+// Providers::search gave them, which breaks ties between equal scores.
+struct ProviderRow {
+    rank: usize,
+    result: SearchResult,
+}
+
+impl ResultList {
+    // `on_action` runs a result's main action when it is clicked, and its
+    // secondary actions from the buttons under an expanded row.
+    pub fn new(
+        app_provider: Rc<AppProvider>,
+        pinned: Weak<PinnedApps>,
+        on_action: impl Fn(&Action) + 'static,
+    ) -> Rc<ResultList> {
+        let store = gio::ListStore::new::<glib::BoxedAnyObject>();
+        let apps_hidden = Rc::new(Cell::new(false));
+
+        // Provider results are only in the store while they answer the search
+        let (filter_provider, filter_hidden) = (app_provider.clone(), apps_hidden.clone());
+        let filter = CustomFilter::new(move |item| match app_of(item) {
+            Some(app) => !filter_hidden.get() && filter_provider.score(&app).is_some(),
+            None => true,
+        });
+        let sorter_provider = app_provider.clone();
+        let sorter = CustomSorter::new(move |a, b| compare_items(a, b, &sorter_provider).into());
+        let filtered = FilterListModel::new(Some(store.clone()), Some(filter.clone()));
+        let sorted = SortListModel::new(Some(filtered), Some(sorter.clone()));
+        let selection = SingleSelection::new(Some(sorted));
+
+        let on_action: OnAction = Rc::new(on_action);
+        let factory = SignalListItemFactory::new();
+        let setup_provider = app_provider.clone();
+        factory.connect_setup(move |_, item| {
+            let Some(item) = item.downcast_ref::<ListItem>() else {
+                return;
+            };
+            let row = create_row();
+
+            let item_weak = item.downgrade();
+            let app_provider = setup_provider.clone();
+            row.add_controller(toggle_pin_on_right_click(pinned.clone(), move || {
+                result_of(&item_weak.upgrade()?.item()?, &app_provider).pin_id
+            }));
+
+            item.set_child(Some(&row));
+        });
+
+        let bind_action = on_action.clone();
+        let bind_provider = app_provider.clone();
+        factory.connect_bind(move |_, item| {
+            let Some(item) = item.downcast_ref::<ListItem>() else {
+                return;
+            };
+            let (Some(row), Some(object)) = (item.child(), item.item()) else {
+                return;
+            };
+            let result = result_of(&object, &bind_provider);
+            bind_row(&row, &result, &bind_action);
+
+            // The row may show another result by the time the image is decoded
+            if let ResultIcon::Image(path) = &result.icon {
+                let item_weak = item.downgrade();
+                image_thumbnail(path, 24, move |texture| {
+                    if let Some(item) = item_weak.upgrade()
+                        && item.item().as_ref() == Some(&object)
+                        && let Some(parts) = item.child().and_then(|row| RowParts::of(&row))
+                    {
+                        parts.icon.set_paintable(Some(&texture));
+                    }
+                });
+            }
+        });
+
+        let list_view = ListView::new(Some(selection.clone()), Some(factory));
+        list_view.add_css_class("app-list");
+        list_view.set_single_click_activate(true);
+
+        let activate_action = on_action.clone();
+        let activate_provider = app_provider.clone();
+        list_view.connect_activate(move |list_view, position| {
+            if let Some(item) = list_view.model().and_then(|model| model.item(position)) {
+                activate_action(&result_of(&item, &activate_provider).activate);
+            }
+        });
+
+        // Right expands the focused row's actions and moves into them, Left
+        // collapses them again and returns to the row.
+        let actions_key_controller = gtk4::EventControllerKey::new();
+        let list_view_weak = list_view.downgrade();
+        actions_key_controller.connect_key_pressed(move |_, key, _, _modifiers| {
+            let Some(list_view) = list_view_weak.upgrade() else {
+                return glib::Propagation::Proceed;
+            };
+            let Some(focused) = list_view.focus_child() else {
+                return glib::Propagation::Proceed;
+            };
+            let Some(row) = focused.first_child().and_then(|row| RowParts::of(&row)) else {
+                return glib::Propagation::Proceed;
+            };
+            if !row.actions_toggle.is_visible() {
+                return glib::Propagation::Proceed;
+            }
+
+            if key == gdk::Key::Right {
+                row.actions_toggle.set_active(true);
+                if let Some(first_action) = row.actions_box.first_child() {
+                    first_action.grab_focus();
+                }
+                return glib::Propagation::Stop;
+            }
+
+            if key == gdk::Key::Left && row.actions_toggle.is_active() {
+                row.actions_toggle.set_active(false);
+                focused.grab_focus();
+                return glib::Propagation::Stop;
+            }
+
+            glib::Propagation::Proceed
+        });
+        list_view.add_controller(actions_key_controller);
+
+        Rc::new(ResultList {
+            app_provider,
+            store,
+            app_count: Cell::new(0),
+            apps_hidden,
+            app_query: RefCell::new(String::new()),
+            filter,
+            sorter,
+            selection,
+            list_view,
+        })
+    }
+
+    pub fn widget(&self) -> &ListView {
+        &self.list_view
+    }
+
+    // Takes the apps from the AppProvider again after the AppWatcher changed them.
+    pub fn reload_apps(&self) {
+        self.app_provider.rescore();
+        let items: Vec<glib::BoxedAnyObject> = self
+            .app_provider
+            .apps()
+            .into_iter()
+            .map(glib::BoxedAnyObject::new)
+            .collect();
+        self.store.splice(0, self.app_count.get(), &items);
+        self.app_count.set(items.len() as u32);
+        self.show_top();
+    }
+
+    // Shows the apps matching `app_query`, or none if it is None (for the
+    // searches that belong to a prefixed provider), with `results` from the
+    // other providers ranked among them. Typing more characters can only drop
+    // app matches and deleting some can only add them, so in those cases the
+    // filter only re-checks the apps that could change sides.
+    pub fn set_results(&self, app_query: Option<&str>, results: Vec<SearchResult>) {
+        let hidden = app_query.is_none();
+        let was_hidden = self.apps_hidden.replace(hidden);
+        let query = app_query.unwrap_or_default().to_lowercase();
+        let previous = self.app_query.replace(query.clone());
+
+        let change = if hidden && was_hidden {
+            None
+        } else if hidden {
+            Some(FilterChange::MoreStrict)
+        } else if was_hidden {
+            Some(FilterChange::Different)
+        } else if query == previous {
+            None
+        } else if query.starts_with(&previous) {
+            Some(FilterChange::MoreStrict)
+        } else if previous.starts_with(&query) {
+            Some(FilterChange::LessStrict)
+        } else {
+            Some(FilterChange::Different)
+        };
+        if let Some(change) = change {
+            self.app_provider.set_query(&query);
+            self.filter.changed(change);
+            if !hidden {
+                self.sorter.changed(SorterChange::Different);
+            }
+        }
+
+        let items: Vec<glib::BoxedAnyObject> = results
+            .into_iter()
+            .enumerate()
+            .map(|(rank, result)| glib::BoxedAnyObject::new(ProviderRow { rank, result }))
+            .collect();
+        let app_count = self.app_count.get();
+        self.store
+            .splice(app_count, self.store.n_items() - app_count, &items);
+        self.show_top();
+    }
+
+    // Re-ranks the apps after the launch history changed.
+    pub fn rescore_apps(&self) {
+        self.app_provider.rescore();
+        self.filter.changed(FilterChange::Different);
+        self.sorter.changed(SorterChange::Different);
+    }
+
+    // The highlighted result, which Enter activates. It starts out on the best
+    // match after every search and moves with the arrow keys.
+    pub fn selected(&self) -> Option<SearchResult> {
+        self.selection
+            .selected_item()
+            .map(|item| result_of(&item, &self.app_provider))
+    }
+
+    // The result at `position`, counting from 0 at the top.
+    pub fn nth(&self, position: u32) -> Option<SearchResult> {
+        self.selection
+            .item(position)
+            .map(|item| result_of(&item, &self.app_provider))
+    }
+
+    // Moves the highlight by `delta` rows, stopping at either end.
+    pub fn move_selection(&self, delta: i64) {
+        let count = self.selection.n_items();
+        if count == 0 {
+            return;
+        }
+        let current = match self.selection.selected() {
+            gtk4::INVALID_LIST_POSITION => 0,
+            position => position as i64,
+        };
+        let target = current.saturating_add(delta).clamp(0, count as i64 - 1);
+        self.select(target as u32);
+    }
+
+    // How many rows fit in the visible part of the list, which is how far
+    // PageUp/PageDown move.
+    pub fn rows_per_page(&self) -> i64 {
+        let count = self.selection.n_items();
+        let Some(adjustment) = self.list_view.vadjustment() else {
+            return 1;
+        };
+        if count == 0 || adjustment.upper() <= 0.0 {
+            return 1;
+        }
+        let row_height = adjustment.upper() / count as f64;
+        ((adjustment.page_size() / row_height) as i64).max(1)
+    }
+
+    fn select(&self, position: u32) {
+        self.selection.set_selected(position);
+        self.list_view
+            .scroll_to(position, ListScrollFlags::NONE, None);
+    }
+
+    fn show_top(&self) {
+        if self.selection.n_items() > 0 {
+            self.select(0);
+        }
+    }
+}
+
+// Items are boxed apps or boxed ProviderRows.
+fn result_of(item: &glib::Object, app_provider: &AppProvider) -> SearchResult {
+    match app_of(item) {
+        Some(app) => app_provider.result(&app),
+        None => provider_row(item).result.clone(),
+    }
+}
+
+fn provider_row(item: &glib::Object) -> std::cell::Ref<'_, ProviderRow> {
+    item.downcast_ref::<glib::BoxedAnyObject>()
+        .expect("result list items are boxed values")
+        .borrow::<ProviderRow>()
+}
+
+// Best score first. Among equal scores the apps come first, in their own
+// order, and then the other results in the order the providers gave them.
+fn compare_items(a: &glib::Object, b: &glib::Object, app_provider: &AppProvider) -> Ordering {
+    match (app_of(a), app_of(b)) {
+        (Some(a), Some(b)) => app_provider.compare(&a, &b),
+        (Some(app), None) => {
+            let score = app_provider.score(&app).unwrap_or(0);
+            Reverse(score)
+                .cmp(&Reverse(provider_row(b).result.score))
+                .then(Ordering::Less)
+        }
+        (None, Some(app)) => {
+            let score = app_provider.score(&app).unwrap_or(0);
+            Reverse(provider_row(a).result.score)
+                .cmp(&Reverse(score))
+                .then(Ordering::Greater)
+        }
+        (None, None) => {
+            let (a, b) = (provider_row(a), provider_row(b));
+            Reverse(a.result.score)
+                .cmp(&Reverse(b.result.score))
+                .then(a.rank.cmp(&b.rank))
+        }
+    }
+}
+
+fn app_of(item: &glib::Object) -> Option<Rc<AppEntry>> {
+    let boxed = item.downcast_ref::<glib::BoxedAnyObject>()?;
+    let app = boxed.try_borrow::<Rc<AppEntry>>().ok()?.clone();
+    Some(app)
+}
+
+// The widgets of a result row that change when it is bound to another result.
+struct RowParts {
+    icon: Image,
+    title_label: Label,
+    subtitle_label: Label,
+    actions_toggle: ToggleButton,
+    actions_box: GtkBox,
+}
+
+impl RowParts {
+    fn of(row: &gtk4::Widget) -> Option<RowParts> {
+        let hbox = row.first_child()?;
+        let icon = hbox.first_child()?;
+        let text_box = icon.next_sibling()?;
+        let title_label = text_box.first_child()?;
+        let subtitle_label = title_label.next_sibling()?;
+        let actions_toggle = text_box.next_sibling()?;
+        let revealer = hbox.next_sibling()?.downcast::<Revealer>().ok()?;
+
+        Some(RowParts {
+            icon: icon.downcast().ok()?,
+            title_label: title_label.downcast().ok()?,
+            subtitle_label: subtitle_label.downcast().ok()?,
+            actions_toggle: actions_toggle.downcast().ok()?,
+            actions_box: revealer.child()?.downcast().ok()?,
+        })
+    }
+}
+
+fn create_row() -> GtkBox {
+    let row = GtkBox::new(Orientation::Vertical, 0);
+
+    let hbox = GtkBox::new(Orientation::Horizontal, 12);
+    hbox.set_margin_top(8);
+    hbox.set_margin_bottom(8);
+    hbox.set_margin_start(12);
+    hbox.set_margin_end(12);
+
+    let icon = Image::new();
+    icon.set_pixel_size(24);
+    hbox.append(&icon);
+
+    // Title, and an optional line about it like where a file is
+    let text_box = GtkBox::new(Orientation::Vertical, 2);
+    text_box.set_hexpand(true);
+    text_box.set_valign(gtk4::Align::Center);
+
+    let title_label = Label::new(None);
+    title_label.add_css_class("app-name");
+    title_label.set_halign(gtk4::Align::Start);
+    text_box.append(&title_label);
+
+    let subtitle_label = Label::new(None);
+    subtitle_label.add_css_class("app-detail");
+    subtitle_label.set_halign(gtk4::Align::Start);
+    subtitle_label.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+    text_box.append(&subtitle_label);
+
+    hbox.append(&text_box);
+
+    // Secondary actions, revealed with the toggle or Right/Left
+    let actions_toggle = ToggleButton::new();
+    actions_toggle.add_css_class("app-actions-toggle");
+    actions_toggle.set_icon_name("pan-down-symbolic");
+    actions_toggle.set_tooltip_text(Some("Actions"));
+    actions_toggle.set_valign(gtk4::Align::Center);
+    hbox.append(&actions_toggle);
+
+    row.append(&hbox);
+
+    let actions_box = GtkBox::new(Orientation::Vertical, 2);
+    actions_box.set_margin_start(48);
+    actions_box.set_margin_end(12);
+    actions_box.set_margin_bottom(6);
+
+    let revealer = Revealer::new();
+    revealer.set_child(Some(&actions_box));
+    actions_toggle
+        .bind_property("active", &revealer, "reveal-child")
+        .sync_create()
+        .build();
+    row.append(&revealer);
+
+    row
+}
+
+fn bind_row(row: &gtk4::Widget, result: &SearchResult, on_action: &OnAction) {
+    let Some(parts) = RowParts::of(row) else {
+        return;
+    };
+
+    let paintable: gdk::Paintable = match &result.icon {
+        ResultIcon::Named(name) => lookup_app_icon(name, 24).upcast(),
+        ResultIcon::File { path, is_dir } => lookup_file_icon(path, *is_dir, 24).upcast(),
+        // Replaced by a thumbnail once it's decoded, see image_thumbnail
+        ResultIcon::Image(_) => lookup_app_icon("image-x-generic", 24).upcast(),
+    };
+    parts.icon.set_paintable(Some(&paintable));
+    parts.title_label.set_text(&result.title);
+    parts
+        .subtitle_label
+        .set_text(result.subtitle.as_deref().unwrap_or_default());
+    parts.subtitle_label.set_visible(result.subtitle.is_some());
+
+    // A recycled row may still be expanded from the result it showed before
+    parts.actions_toggle.set_active(false);
+    parts.actions_toggle.set_visible(!result.actions.is_empty());
+    while let Some(child) = parts.actions_box.first_child() {
+        parts.actions_box.remove(&child);
+    }
+
+    let fallback_icon = match &result.icon {
+        ResultIcon::Named(name) => name.as_str(),
+        ResultIcon::File { .. } => "system-run",
+        ResultIcon::Image(_) => "image-x-generic",
+    };
+    for action in &result.actions {
+        let action_btn = create_action_button(action, fallback_icon);
+        let action = action.clone();
+        let on_action = on_action.clone();
+        action_btn.connect_clicked(move |_| on_action(&action));
+        parts.actions_box.append(&action_btn);
+    }
+}
+
+fn create_action_button(action: &Action, fallback_icon: &str) -> Button {
+    let button = Button::new();
+    button.add_css_class("app-action");
+
+    let hbox = GtkBox::new(Orientation::Horizontal, 8);
+    hbox.set_margin_top(4);
+    hbox.set_margin_bottom(4);
+
+    let icon = lazy_app_icon(action.icon.as_deref().unwrap_or(fallback_icon), 16);
+    hbox.append(&icon);
+
+    let name_label = Label::new(Some(&action.name));
+    name_label.add_css_class("app-action-name");
+    name_label.set_halign(gtk4::Align::Start);
+    hbox.append(&name_label);
+
+    button.set_child(Some(&hbox));
+    button
+}
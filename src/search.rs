@@ -0,0 +1,260 @@
+use std::cmp::Reverse;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+// Ranks above any match score, for results that answer the query itself, like
+// the value of an arithmetic expression.
+pub const ANSWER_SCORE: i32 = i32::MAX;
+
+// Something that contributes rows to the results list. Providers without
+// prefixes are asked on every search and their results merged; a provider with
+// prefixes gets the searches starting with one of them to itself.
+pub trait SearchProvider {
+    fn prefixes(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    // Results for the entry text, prefix included, in the provider's own order.
+    fn search(&self, text: &str) -> Vec<SearchResult>;
+}
+
+// A row of the results list. Enter or a click runs `activate`; `actions` are
+// shown under the row when it is expanded, and those with a `modifier` also
+// run on Enter with that modifier held.
+#[derive(Clone)]
+pub struct SearchResult {
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub icon: ResultIcon,
+    pub score: i32,
+    pub activate: Action,
+    pub actions: Vec<Action>,
+    // The desktop file ID of an app result, which Ctrl+P and right clicks pin
+    pub pin_id: Option<String>,
+    // What Ctrl+P pins or unpins for other results, like a clipboard entry
+    pub toggle_pin: Option<Rc<dyn Fn()>>,
+    // Entry text that Tab completes to
+    pub completion: Option<String>,
+    // Drops what ranked this result where it is (launch or command history),
+    // or the result itself for clipboard entries, for Shift+Delete
+    pub forget: Option<Rc<dyn Fn()>>,
+}
+
+impl SearchResult {
+    pub fn new(title: &str, icon: ResultIcon, activate: Action) -> SearchResult {
+        SearchResult {
+            title: title.to_string(),
+            subtitle: None,
+            icon,
+            score: 0,
+            activate,
+            actions: Vec::new(),
+            pin_id: None,
+            toggle_pin: None,
+            completion: None,
+            forget: None,
+        }
+    }
+
+    // The action Enter runs with `modifier` held, if any.
+    pub fn action_for(&self, modifier: Modifier) -> Option<&Action> {
+        self.actions
+            .iter()
+            .find(|action| action.modifier == Some(modifier))
+    }
+}
+
+#[derive(Clone)]
+pub enum ResultIcon {
+    // An icon theme name
+    Named(String),
+    // The icon for the file's type
+    File { path: PathBuf, is_dir: bool },
+    // A thumbnail of the image file
+    Image(PathBuf),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Modifier {
+    Shift,
+    Control,
+}
+
+#[derive(Clone)]
+pub struct Action {
+    pub name: String,
+    pub icon: Option<String>,
+    pub modifier: Option<Modifier>,
+    pub run: Rc<dyn Fn() -> Result<Outcome, String>>,
+}
+
+impl Action {
+    pub fn new(name: &str, run: impl Fn() -> Result<Outcome, String> + 'static) -> Action {
+        Action {
+            name: name.to_string(),
+            icon: None,
+            modifier: None,
+            run: Rc::new(run),
+        }
+    }
+}
+
+// What the popup does after an action succeeded; failures show their message
+// under the search entry.
+pub enum Outcome {
+    // Something was launched or opened, so the popup closes
+    Done,
+    // Text to put on the clipboard. The popup stays open, since the clipboard
+    // is served by this process.
+    Copy(String),
+    // Like Copy, for the image in the file
+    CopyImage(PathBuf),
+}
+
+#[derive(Default)]
+pub struct Providers {
+    providers: Vec<Rc<dyn SearchProvider>>,
+}
+
+impl Providers {
+    pub fn register(&mut self, provider: Rc<dyn SearchProvider>) {
+        self.providers.push(provider);
+    }
+
+    // Searches with the provider whose prefix `text` starts with, or else with
+    // every provider that has none. Results are merged best score first; equal
+    // scores keep the order of registration and then each provider's own.
+    pub fn search(&self, text: &str) -> Vec<SearchResult> {
+        let mut results: Vec<SearchResult> = match self.prefixed(text) {
+            Some(provider) => provider.search(text),
+            None => self
+                .providers
+                .iter()
+                .filter(|provider| provider.prefixes().is_empty())
+                .flat_map(|provider| provider.search(text))
+                .collect(),
+        };
+        results.sort_by_key(|result| Reverse(result.score));
+        results
+    }
+
+    // Whether `text` starts with a provider's prefix, so that only that
+    // provider answers it.
+    pub fn is_prefixed(&self, text: &str) -> bool {
+        self.prefixed(text).is_some()
+    }
+
+    fn prefixed(&self, text: &str) -> Option<&Rc<dyn SearchProvider>> {
+        let trimmed = text.trim_start();
+        self.providers.iter().find(|provider| {
+            provider
+                .prefixes()
+                .iter()
+                .any(|prefix| trimmed.starts_with(prefix))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed {
+        prefixes: Vec<&'static str>,
+        results: Vec<(&'static str, i32)>,
+    }
+
+    impl SearchProvider for Fixed {
+        fn prefixes(&self) -> Vec<&str> {
+            self.prefixes.clone()
+        }
+
+        fn search(&self, text: &str) -> Vec<SearchResult> {
+            self.results
+                .iter()
+                .map(|(title, score)| {
+                    let activate = Action::new("Open", || Ok(Outcome::Done));
+                    let mut result = SearchResult::new(
+                        &format!("{} {}", title, text),
+                        ResultIcon::Named("icon".to_string()),
+                        activate,
+                    );
+                    result.score = *score;
+                    result
+                })
+                .collect()
+        }
+    }
+
+    fn fixed(prefixes: &[&'static str], results: &[(&'static str, i32)]) -> Fixed {
+        Fixed {
+            prefixes: prefixes.to_vec(),
+            results: results.to_vec(),
+        }
+    }
+
+    fn providers(fixed: Vec<Fixed>) -> Providers {
+        let mut providers = Providers::default();
+        for provider in fixed {
+            providers.register(Rc::new(provider));
+        }
+        providers
+    }
+
+    fn titles(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().map(|result| result.title.as_str()).collect()
+    }
+
+    #[test]
+    fn unprefixed_providers_are_merged_by_score() {
+        let providers = providers(vec![
+            fixed(&[], &[("a1", 10), ("a2", 0), ("a3", 0)]),
+            fixed(&[], &[("b1", ANSWER_SCORE), ("b2", 10)]),
+            fixed(&[">"], &[("cmd", 50)]),
+        ]);
+
+        assert_eq!(
+            titles(&providers.search("q")),
+            vec!["b1 q", "a1 q", "b2 q", "a2 q", "a3 q"]
+        );
+    }
+
+    #[test]
+    fn a_prefix_routes_the_search_to_its_provider_alone() {
+        let providers = providers(vec![
+            fixed(&[], &[("app", 10)]),
+            fixed(&[">", "!"], &[("cmd", 0)]),
+            fixed(&["/", "~"], &[("file", 0)]),
+        ]);
+
+        assert_eq!(titles(&providers.search("> ls")), vec!["cmd > ls"]);
+        assert_eq!(titles(&providers.search("  !ls")), vec!["cmd   !ls"]);
+        assert_eq!(titles(&providers.search("~/notes")), vec!["file ~/notes"]);
+        assert_eq!(titles(&providers.search("notes")), vec!["app notes"]);
+        assert!(providers.is_prefixed("  !ls"));
+        assert!(!providers.is_prefixed("notes"));
+    }
+
+    #[test]
+    fn modifier_actions_are_found_by_modifier() {
+        let mut result = SearchResult::new(
+            "ls",
+            ResultIcon::Named("utilities-terminal".to_string()),
+            Action::new("Run", || Ok(Outcome::Done)),
+        );
+        let mut in_terminal = Action::new("Run in Terminal", || Ok(Outcome::Done));
+        in_terminal.modifier = Some(Modifier::Shift);
+        result
+            .actions
+            .push(Action::new("Other", || Ok(Outcome::Done)));
+        result.actions.push(in_terminal);
+
+        assert_eq!(
+            result
+                .action_for(Modifier::Shift)
+                .map(|action| action.name.as_str()),
+            Some("Run in Terminal")
+        );
+        assert!(result.action_for(Modifier::Control).is_none());
+    }
+}
@@ -0,0 +1,157 @@
+use std::env;
+use std::path::PathBuf;
+
+use crate::apps::{find_executable, search_path};
+use crate::config::Config;
+use crate::exec;
+
+// Terminals we know how to hand a command to, in fallback order, with the
+// arguments that must precede the command.
+const KNOWN_TERMINALS: &[(&str, &[&str])] = &[
+    ("x-terminal-emulator", &["-e"]),
+    ("kitty", &[]),
+    ("foot", &[]),
+    ("alacritty", &["-e"]),
+    ("wezterm", &["start", "--"]),
+    ("ghostty", &["-e"]),
+    ("gnome-terminal", &["--"]),
+    ("kgx", &["--"]),
+    ("konsole", &["-e"]),
+    ("xfce4-terminal", &["-x"]),
+    ("mate-terminal", &["-x"]),
+    ("terminator", &["-x"]),
+    ("tilix", &["-e"]),
+    ("lxterminal", &["-e"]),
+    ("urxvt", &["-e"]),
+    ("st", &["-e"]),
+    ("xterm", &["-e"]),
+];
+
+// Prefixes `argv` with a terminal emulator invocation. The terminal comes from
+// the config file, then $TERMINAL, then the first installed known terminal.
+pub fn wrap_in_terminal(argv: &[String]) -> Result<Vec<String>, String> {
+    let mut command = terminal_command().ok_or("no terminal emulator found")?;
+    command.extend(argv.iter().cloned());
+    Ok(command)
+}
+
+fn terminal_command() -> Option<Vec<String>> {
+    let configured = Config::load()
+        .terminal
+        .into_iter()
+        .chain(env::var("TERMINAL").ok().filter(|s| !s.is_empty()));
+    choose_terminal(configured, &search_path())
+}
+
+// The first of the configured `specs` that is installed in `path`, or else the
+// first installed known terminal.
+fn choose_terminal(
+    specs: impl IntoIterator<Item = String>,
+    path: &[PathBuf],
+) -> Option<Vec<String>> {
+    for spec in specs {
+        if let Some(command) = command_from_spec(&spec)
+            && find_executable(&command[0], path).is_some()
+        {
+            return Some(command);
+        }
+    }
+
+    KNOWN_TERMINALS
+        .iter()
+        .find(|(name, _)| find_executable(name, path).is_some())
+        .map(|(name, args)| {
+            std::iter::once(name.to_string())
+                .chain(args.iter().map(|arg| arg.to_string()))
+                .collect()
+        })
+}
+
+// A bare program name gets the arguments we know it needs (`-e` if we don't
+// know it); anything longer is taken to already end with the right flag, e.g.
+// `wezterm start --`.
+fn command_from_spec(spec: &str) -> Option<Vec<String>> {
+    let mut command = exec::tokenize(spec).ok()?;
+    if command.len() != 1 {
+        return (!command.is_empty()).then_some(command);
+    }
+
+    let program = command[0].rsplit('/').next().unwrap_or_default();
+    match KNOWN_TERMINALS.iter().find(|(name, _)| *name == program) {
+        Some((_, args)) => command.extend(args.iter().map(|arg| arg.to_string())),
+        None => command.push("-e".to_string()),
+    }
+    Some(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn bare_names_get_the_arguments_their_terminal_needs() {
+        assert_eq!(
+            command_from_spec("wezterm"),
+            Some(strings(&["wezterm", "start", "--"]))
+        );
+        assert_eq!(
+            command_from_spec("/usr/bin/gnome-terminal"),
+            Some(strings(&["/usr/bin/gnome-terminal", "--"]))
+        );
+        assert_eq!(command_from_spec("foot"), Some(strings(&["foot"])));
+        assert_eq!(
+            command_from_spec("myterm"),
+            Some(strings(&["myterm", "-e"]))
+        );
+    }
+
+    #[test]
+    fn longer_specs_are_kept_as_written() {
+        assert_eq!(
+            command_from_spec("kitty --single-instance"),
+            Some(strings(&["kitty", "--single-instance"]))
+        );
+        assert_eq!(
+            command_from_spec("\"my term\" -x"),
+            Some(strings(&["my term", "-x"]))
+        );
+    }
+
+    #[test]
+    fn empty_or_unparsable_specs_are_rejected() {
+        assert_eq!(command_from_spec(""), None);
+        assert_eq!(command_from_spec("   "), None);
+        assert_eq!(command_from_spec("\"unterminated"), None);
+    }
+
+    #[test]
+    fn installed_configured_terminals_win_over_known_ones() {
+        let dir = env::temp_dir().join(format!("bitpop-terminal-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["foot", "xterm"] {
+            let path = dir.join(name);
+            fs::write(&path, "").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let path = std::slice::from_ref(&dir);
+
+        let specs = strings(&["missing-term", "xterm -fa Mono"]);
+        assert_eq!(
+            choose_terminal(specs, path),
+            Some(strings(&["xterm", "-fa", "Mono"]))
+        );
+        assert_eq!(
+            choose_terminal(strings(&["missing-term"]), path),
+            Some(strings(&["foot"]))
+        );
+        assert_eq!(choose_terminal(Vec::new(), &[]), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
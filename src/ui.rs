@@ -1,22 +1,33 @@
 use chrono::Local;
 use gtk4::prelude::*;
 use gtk4::{
-    Application, ApplicationWindow, Box as GtkBox, Button, Entry, Label, ListBox, ListBoxRow,
-    Orientation, ScrolledWindow, glib,
+    Application, ApplicationWindow, Box as GtkBox, Button, Entry, Label, ListView, Orientation,
+    ScrolledWindow, glib,
 };
 use std::cell::RefCell;
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::PathBuf;
 use std::process::Command;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 
-use crate::apps::{AppEntry, launch_app, load_desktop_apps};
+use crate::app_watcher::AppWatcher;
+use crate::apps::AppEntry;
+use crate::clipboard::preview;
+use crate::config::Config;
 use crate::icons::load_app_icon;
+use crate::pinned::PinnedApps;
+use crate::providers::{
+    AppProvider, CalculatorProvider, ClipboardProvider, CommandProvider, ConversionProvider,
+    FileProvider, PluginProvider, WindowProvider,
+};
+use crate::result_list::ResultList;
+use crate::search::{Action, Modifier, Outcome, Providers};
 use crate::system::{
     airplane_mode, toggle_bluetooth, toggle_wifi, update_battery, update_bluetooth_status,
     update_wifi_status,
 };
+use crate::windows::focus_or_launch;
 
 const LOCK_FILE: &str = "/tmp/bitpop.lock";
 
@@ -87,7 +98,7 @@ pub fn build_ui(app: &Application) {
 
     build_clock_section(&main_box);
     build_battery_section(&main_box);
-    let (search_entry, _app_list) = build_app_search_section(&main_box, &window);
+    let (search_entry, _result_list) = build_app_search_section(&main_box, &window);
     let wifi_btn = build_quick_controls_section(&main_box);
     build_power_section(&main_box, &window);
     build_hint_section(&main_box);
@@ -180,123 +191,344 @@ fn build_battery_section(main_box: &GtkBox) {
     main_box.append(&battery_card);
 }
 
-fn build_app_search_section(main_box: &GtkBox, window: &ApplicationWindow) -> (Entry, ListBox) {
+fn build_app_search_section(main_box: &GtkBox, window: &ApplicationWindow) -> (Entry, ListView) {
     let search_label = Label::new(Some("LAUNCH APP"));
     search_label.add_css_class("section-label");
     search_label.set_halign(gtk4::Align::Start);
     main_box.append(&search_label);
 
+    // Filled in by the AppWatcher below once apps are loaded off the main thread
+    let all_apps = Rc::new(RefCell::new(Vec::new()));
+
+    let error_label = Label::new(None);
+    error_label.add_css_class("launch-error");
+    error_label.set_halign(gtk4::Align::Start);
+    error_label.set_wrap(true);
+    error_label.set_visible(false);
+
+    let notice_label = Label::new(None);
+    notice_label.add_css_class("search-notice");
+    notice_label.set_halign(gtk4::Align::Start);
+    notice_label.set_single_line_mode(true);
+    notice_label.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+    notice_label.set_visible(false);
+
+    // Pinned apps grid
+    let window_weak = window.downgrade();
+    let error_label_weak = error_label.downgrade();
+    let error_label_for_pins = error_label.downgrade();
+    let pinned = PinnedApps::new(
+        all_apps.clone(),
+        move |app| {
+            if let (Some(window), Some(error_label)) =
+                (window_weak.upgrade(), error_label_weak.upgrade())
+            {
+                activate_app(app, &window, &error_label);
+            }
+        },
+        move |err| {
+            if let Some(error_label) = error_label_for_pins.upgrade() {
+                show_error(err, &error_label);
+            }
+        },
+    );
+    main_box.append(pinned.widget());
+
     let search_entry = Entry::new();
     search_entry.add_css_class("search-entry");
     search_entry.set_placeholder_text(Some("Type to search..."));
     search_entry.set_halign(gtk4::Align::Fill);
     main_box.append(&search_entry);
 
-    let app_list = ListBox::new();
-    app_list.add_css_class("app-list");
-    app_list.set_selection_mode(gtk4::SelectionMode::Single);
-    app_list.set_show_separators(false);
+    main_box.append(&error_label);
+    main_box.append(&notice_label);
+
+    let window_weak = window.downgrade();
+    let error_label_weak = error_label.downgrade();
+    let notice_label_weak = notice_label.downgrade();
+    let app_provider = AppProvider::new();
+    let result_list = ResultList::new(
+        app_provider.clone(),
+        Rc::downgrade(&pinned),
+        move |action| {
+            if let (Some(window), Some(error_label), Some(notice_label)) = (
+                window_weak.upgrade(),
+                error_label_weak.upgrade(),
+                notice_label_weak.upgrade(),
+            ) {
+                run_action(action, &window, &error_label, &notice_label);
+            }
+        },
+    );
 
     let scrolled_window = ScrolledWindow::new();
     scrolled_window.add_css_class("app-scrolled-window");
-    scrolled_window.set_child(Some(&app_list));
+    scrolled_window.set_child(Some(result_list.widget()));
     scrolled_window.set_vexpand(true);
     scrolled_window.set_max_content_height(150);
     scrolled_window.set_propagate_natural_height(true);
     scrolled_window.set_policy(gtk4::PolicyType::Never, gtk4::PolicyType::Automatic);
     main_box.append(&scrolled_window);
 
-    let all_apps = Rc::new(RefCell::new(load_desktop_apps()));
-    update_app_list(&app_list, &all_apps.borrow(), "", window);
+    // Apps, calculations and conversions answer every search; commands, files,
+    // windows and the clipboard only the searches starting with their prefix. Providers whose data
+    // arrives later search again for the entry's current text when it does. The
+    // apps stay in the result list's own model, which ranks these results among
+    // them by score, see ResultList.
+    let entry_for_providers = search_entry.downgrade();
+    let result_list_for_providers = result_list.clone();
+    let providers = Rc::new_cyclic(|providers_weak: &Weak<Providers>| {
+        // For providers whose results arrive after the search that asked for them
+        let providers_weak = providers_weak.clone();
+        let search_again: Rc<dyn Fn()> = Rc::new(move || {
+            if let (Some(providers), Some(entry)) =
+                (providers_weak.upgrade(), entry_for_providers.upgrade())
+            {
+                show_search(&result_list_for_providers, &providers, &entry.text());
+            }
+        });
 
-    let app_list_weak = app_list.downgrade();
-    let all_apps_clone = all_apps.clone();
-    let window_weak = window.downgrade();
+        let mut providers = Providers::default();
+        providers.register(Rc::new(CalculatorProvider));
+        providers.register(Rc::new(ConversionProvider));
+        let on_executables = search_again.clone();
+        providers.register(CommandProvider::new(move || on_executables()));
+        let on_index = search_again.clone();
+        providers.register(FileProvider::new(move || on_index()));
+        providers.register(WindowProvider::new(app_provider.clone()));
+        providers.register(ClipboardProvider::new());
+        // After the built-in ones, so a plugin can't take over their prefixes
+        for plugin in Config::load().plugins {
+            let on_results = search_again.clone();
+            providers.register(PluginProvider::new(plugin, move || on_results()));
+        }
+        providers
+    });
+
+    let result_list_clone = result_list.clone();
+    let providers_clone = providers.clone();
+    let error_label_weak = error_label.downgrade();
+    let notice_label_weak = notice_label.downgrade();
     search_entry.connect_changed(move |entry| {
-        let query = entry.text().to_string().to_lowercase();
-        if let Some(app_list) = app_list_weak.upgrade() {
-            if let Some(window) = window_weak.upgrade() {
-                update_app_list(&app_list, &all_apps_clone.borrow(), &query, &window);
-            }
+        if let (Some(error_label), Some(notice_label)) =
+            (error_label_weak.upgrade(), notice_label_weak.upgrade())
+        {
+            error_label.set_visible(false);
+            notice_label.set_visible(false);
         }
+        show_search(&result_list_clone, &providers_clone, &entry.text());
     });
 
-    let all_apps_for_enter = all_apps.clone();
+    // Apps stream in as they are loaded, and installed, removed or edited apps
+    // show up without a restart
+    let all_apps_for_watcher = all_apps.clone();
+    let pinned_for_watcher = Rc::downgrade(&pinned);
+    let result_list_for_watcher = result_list.clone();
+    let providers_for_watcher = providers.clone();
+    let entry_for_watcher = search_entry.downgrade();
+    AppWatcher::start(move |apps| {
+        app_provider.set_apps(&apps);
+        result_list_for_watcher.reload_apps();
+        *all_apps_for_watcher.borrow_mut() = apps;
+        if let Some(pinned) = pinned_for_watcher.upgrade() {
+            pinned.rebuild();
+        }
+        if let Some(entry) = entry_for_watcher.upgrade() {
+            show_search(
+                &result_list_for_watcher,
+                &providers_for_watcher,
+                &entry.text(),
+            );
+        }
+    });
+
+    let result_list_for_enter = result_list.clone();
     let window_for_enter = window.downgrade();
-    search_entry.connect_activate(move |entry| {
-        let query = entry.text().to_string().to_lowercase();
-        let apps = all_apps_for_enter.borrow();
-        let filtered: Vec<&AppEntry> = apps
-            .iter()
-            .filter(|app| app.name.to_lowercase().contains(&query))
-            .take(1)
-            .collect();
-
-        if let Some(app) = filtered.first() {
-            let app_clone = (*app).clone();
-            drop(apps);
-            launch_app(&app_clone);
-            if let Some(window) = window_for_enter.upgrade() {
-                window.close();
-            }
+    let error_label_for_enter = error_label.downgrade();
+    let notice_label_for_enter = notice_label.downgrade();
+    search_entry.connect_activate(move |_| {
+        if let (Some(result), Some(window), Some(error_label), Some(notice_label)) = (
+            result_list_for_enter.selected(),
+            window_for_enter.upgrade(),
+            error_label_for_enter.upgrade(),
+            notice_label_for_enter.upgrade(),
+        ) {
+            run_action(&result.activate, &window, &error_label, &notice_label);
         }
     });
 
-    (search_entry, app_list)
-}
+    // Up/Down, PageUp/PageDown and Home/End move the highlighted result while
+    // typing continues in the entry, Ctrl+1..9 activate the Nth result, Enter
+    // with Shift or Ctrl held runs the result's action for that modifier, and
+    // Tab completes the entry to it. This runs in the capture phase, before
+    // the entry's own bindings for these keys.
+    let navigation_controller = gtk4::EventControllerKey::new();
+    navigation_controller.set_propagation_phase(gtk4::PropagationPhase::Capture);
+    let result_list_for_navigation = result_list.clone();
+    let window_for_navigation = window.downgrade();
+    let error_label_for_navigation = error_label.downgrade();
+    let notice_label_for_navigation = notice_label.downgrade();
+    navigation_controller.connect_key_pressed(move |controller, key, _, modifiers| {
+        let run = |action: &Action| {
+            if let (Some(window), Some(error_label), Some(notice_label)) = (
+                window_for_navigation.upgrade(),
+                error_label_for_navigation.upgrade(),
+                notice_label_for_navigation.upgrade(),
+            ) {
+                run_action(action, &window, &error_label, &notice_label);
+            }
+        };
+        let control = modifiers.contains(gtk4::gdk::ModifierType::CONTROL_MASK);
+
+        if key == gtk4::gdk::Key::Return || key == gtk4::gdk::Key::KP_Enter {
+            let modifier = if modifiers.contains(gtk4::gdk::ModifierType::SHIFT_MASK) {
+                Modifier::Shift
+            } else if control {
+                Modifier::Control
+            } else {
+                return glib::Propagation::Proceed;
+            };
+            let Some(result) = result_list_for_navigation.selected() else {
+                return glib::Propagation::Proceed;
+            };
+            let Some(action) = result.action_for(modifier) else {
+                return glib::Propagation::Proceed;
+            };
+            run(action);
+            return glib::Propagation::Stop;
+        }
 
-fn update_app_list(list_box: &ListBox, apps: &[AppEntry], query: &str, window: &ApplicationWindow) {
-    while let Some(child) = list_box.first_child() {
-        list_box.remove(&child);
-    }
+        if key == gtk4::gdk::Key::Tab
+            && let Some(completion) = result_list_for_navigation
+                .selected()
+                .and_then(|result| result.completion)
+            && let Some(entry) = controller.widget().and_downcast::<Entry>()
+        {
+            entry.set_text(&completion);
+            entry.set_position(-1);
+            return glib::Propagation::Stop;
+        }
 
-    let filtered: Vec<&AppEntry> = apps
-        .iter()
-        .filter(|app| app.name.to_lowercase().contains(query))
-        .take(10)
-        .collect();
-
-    for app in filtered {
-        let row = create_app_row(app);
-        let app_clone = app.clone();
-        let window_weak = window.downgrade();
-
-        row.connect_activate(move |_| {
-            launch_app(&app_clone);
-            if let Some(window) = window_weak.upgrade() {
-                window.close();
+        if control {
+            let Some(n) = key
+                .to_unicode()
+                .and_then(|c| c.to_digit(10))
+                .filter(|n| *n >= 1)
+            else {
+                return glib::Propagation::Proceed;
+            };
+            if let Some(result) = result_list_for_navigation.nth(n - 1) {
+                run(&result.activate);
             }
-        });
+            return glib::Propagation::Stop;
+        }
 
-        list_box.append(&row);
-    }
-}
+        let page = result_list_for_navigation.rows_per_page();
+        let delta = match key {
+            gtk4::gdk::Key::Up => -1,
+            gtk4::gdk::Key::Down => 1,
+            gtk4::gdk::Key::Page_Up => -page,
+            gtk4::gdk::Key::Page_Down => page,
+            gtk4::gdk::Key::Home => i64::MIN,
+            gtk4::gdk::Key::End => i64::MAX,
+            _ => return glib::Propagation::Proceed,
+        };
+        result_list_for_navigation.move_selection(delta);
+        glib::Propagation::Stop
+    });
+    search_entry.add_controller(navigation_controller);
+
+    // Shift+Delete forgets the history of the selected result (the one Enter
+    // would activate), such as an app's launches or a recent command, or
+    // deletes a clipboard entry, and Ctrl+P pins or unpins an app or clipboard
+    // entry. Capture phase again, since the entry binds Shift+Delete to cut.
+    let selected_result_controller = gtk4::EventControllerKey::new();
+    selected_result_controller.set_propagation_phase(gtk4::PropagationPhase::Capture);
+    let result_list_for_keys = result_list.clone();
+    selected_result_controller.connect_key_pressed(move |controller, key, _, modifiers| {
+        let forget = key == gtk4::gdk::Key::Delete
+            && modifiers.contains(gtk4::gdk::ModifierType::SHIFT_MASK);
+        let pin = (key == gtk4::gdk::Key::p || key == gtk4::gdk::Key::P)
+            && modifiers.contains(gtk4::gdk::ModifierType::CONTROL_MASK);
+        if !forget && !pin {
+            return glib::Propagation::Proceed;
+        }
+        let Some(result) = result_list_for_keys.selected() else {
+            return glib::Propagation::Stop;
+        };
+
+        if pin && let Some(id) = result.pin_id {
+            pinned.toggle(&id);
+            return glib::Propagation::Stop;
+        }
 
-fn create_app_row(app: &AppEntry) -> ListBoxRow {
-    let row = ListBoxRow::new();
-    row.add_css_class("app-row");
-    row.set_selectable(true);
-    row.set_activatable(true);
+        // Search again so the list reflects the new order
+        let change = if pin {
+            result.toggle_pin
+        } else {
+            result.forget
+        };
+        if let Some(change) = change
+            && let Some(entry) = controller.widget().and_downcast::<Entry>()
+        {
+            change();
+            result_list_for_keys.rescore_apps();
+            show_search(&result_list_for_keys, &providers, &entry.text());
+        }
+        glib::Propagation::Stop
+    });
+    search_entry.add_controller(selected_result_controller);
 
-    let hbox = GtkBox::new(Orientation::Horizontal, 12);
-    hbox.set_margin_top(8);
-    hbox.set_margin_bottom(8);
-    hbox.set_margin_start(12);
-    hbox.set_margin_end(12);
+    (search_entry, result_list.widget().clone())
+}
 
-    // App icon
-    let icon = load_app_icon(&app.icon, 24);
-    hbox.append(&icon);
+// Searches with every provider, and with the apps unless a provider's prefix
+// claims the search.
+fn show_search(result_list: &ResultList, providers: &Providers, text: &str) {
+    let app_query = (!providers.is_prefixed(text)).then_some(text);
+    result_list.set_results(app_query, providers.search(text));
+}
+
+// Closes the popup once something was launched or opened; if that failed the
+// reason is shown under the search entry and the popup stays open. It also
+// stays open after copying: the clipboard is served by this process, so
+// closing would take the copied text with it before it can be pasted.
+fn run_action(
+    action: &Action,
+    window: &ApplicationWindow,
+    error_label: &Label,
+    notice_label: &Label,
+) {
+    match (action.run)() {
+        Ok(Outcome::Done) => window.close(),
+        Ok(Outcome::Copy(text)) => {
+            window.clipboard().set_text(&text);
+            notice_label.set_text(&format!("Copied {} to the clipboard", preview(&text)));
+            notice_label.set_visible(true);
+        }
+        Ok(Outcome::CopyImage(path)) => match gtk4::gdk::Texture::from_filename(&path) {
+            Ok(texture) => {
+                window.clipboard().set_texture(&texture);
+                notice_label.set_text("Copied the image to the clipboard");
+                notice_label.set_visible(true);
+            }
+            Err(err) => show_error(&format!("Couldn't load the image: {}", err), error_label),
+        },
+        Err(err) => show_error(&err, error_label),
+    }
+}
 
-    // App name
-    let name_label = Label::new(Some(&app.name));
-    name_label.add_css_class("app-name");
-    name_label.set_halign(gtk4::Align::Start);
-    name_label.set_hexpand(true);
-    hbox.append(&name_label);
+// Pinned tiles launch apps directly rather than through a search result.
+fn activate_app(app: &AppEntry, window: &ApplicationWindow, error_label: &Label) {
+    match focus_or_launch(app) {
+        Ok(()) => window.close(),
+        Err(err) => show_error(&err, error_label),
+    }
+}
 
-    row.set_child(Some(&hbox));
-    row
+fn show_error(err: &str, error_label: &Label) {
+    error_label.set_text(err);
+    error_label.set_visible(true);
 }
 
 fn build_quick_controls_section(main_box: &GtkBox) -> Button {
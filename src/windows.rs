@@ -0,0 +1,517 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::env;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::APP_ID;
+use crate::apps::{AppEntry, launch_app};
+use crate::fuzzy;
+use crate::history;
+
+// Searches starting with this list the open windows, e.g. "@term".
+pub const PREFIXES: &[&str] = &["@"];
+
+// A compositor that hangs doesn't get to freeze the popup for longer than this.
+const IPC_TIMEOUT: Duration = Duration::from_secs(1);
+
+// i3 IPC message types, shared by Sway
+const I3_RUN_COMMAND: u32 = 0;
+const I3_GET_TREE: u32 = 4;
+const I3_MAGIC: &[u8] = b"i3-ipc";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Window {
+    // The con_id on Sway/i3, the address on Hyprland
+    pub id: String,
+    pub title: String,
+    // The Wayland app_id, or the X11 WM_CLASS class
+    pub app_id: String,
+    pub workspace: Option<String>,
+    // 0 for the focused window, then 1, 2... in order of last focus. i3 and
+    // Sway only say which window is focused, so the others have None there.
+    pub focus_order: Option<u32>,
+}
+
+impl Window {
+    pub fn is_focused(&self) -> bool {
+        self.focus_order == Some(0)
+    }
+}
+
+// The window manager whose windows can be listed and focused, found through
+// the socket it advertises in the environment.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Compositor {
+    // Sway or i3, which speak the same IPC protocol
+    I3(PathBuf),
+    Hyprland(PathBuf),
+}
+
+impl Compositor {
+    pub fn detect() -> Option<Compositor> {
+        Compositor::from_env(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+    }
+
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Option<Compositor> {
+        if let Some(socket) = var("SWAYSOCK").or_else(|| var("I3SOCK")) {
+            return Some(Compositor::I3(PathBuf::from(socket)));
+        }
+
+        // Hyprland moved its sockets from /tmp to the runtime directory in 0.40
+        let signature = var("HYPRLAND_INSTANCE_SIGNATURE")?;
+        let runtime_socket = var("XDG_RUNTIME_DIR").map(|dir| {
+            Path::new(&dir)
+                .join("hypr")
+                .join(&signature)
+                .join(".socket.sock")
+        });
+        let socket = runtime_socket
+            .filter(|socket| socket.exists())
+            .unwrap_or_else(|| Path::new("/tmp/hypr").join(&signature).join(".socket.sock"));
+        Some(Compositor::Hyprland(socket))
+    }
+
+    // The open windows, in the compositor's order (workspace by workspace).
+    pub fn windows(&self) -> Result<Vec<Window>, String> {
+        match self {
+            Compositor::I3(socket) => {
+                let tree = i3_request(socket, I3_GET_TREE, "").map_err(ipc_error)?;
+                let tree: Value = serde_json::from_str(&tree).map_err(|err| err.to_string())?;
+                let mut windows = Vec::new();
+                i3_windows(&tree, None, &mut windows);
+                Ok(windows)
+            }
+            Compositor::Hyprland(socket) => {
+                let clients = hyprland_request(socket, "j/clients").map_err(ipc_error)?;
+                hyprland_windows(&clients)
+            }
+        }
+    }
+
+    pub fn focus(&self, window: &Window) -> Result<(), String> {
+        match self {
+            Compositor::I3(socket) => {
+                let command = format!("[con_id={}] focus", window.id);
+                let reply = i3_request(socket, I3_RUN_COMMAND, &command).map_err(ipc_error)?;
+                let succeeded = serde_json::from_str::<Vec<Value>>(&reply).is_ok_and(|results| {
+                    results
+                        .iter()
+                        .all(|result| result["success"].as_bool() == Some(true))
+                });
+                if succeeded {
+                    Ok(())
+                } else {
+                    Err(format!("Couldn't focus {}", window.title))
+                }
+            }
+            Compositor::Hyprland(socket) => {
+                let command = format!("dispatch focuswindow address:{}", window.id);
+                match hyprland_request(socket, &command)
+                    .map_err(ipc_error)?
+                    .trim()
+                {
+                    "ok" => Ok(()),
+                    reply => Err(format!("Couldn't focus {}: {}", window.title, reply)),
+                }
+            }
+        }
+    }
+}
+
+// Focuses the window of `app` that had the focus last if it has one open, or
+// else launches it, which is also what happens when no supported window
+// manager is running. Focusing counts as a launch for the app's ranking.
+pub fn focus_or_launch(app: &AppEntry) -> Result<(), String> {
+    if let Some(compositor) = Compositor::detect()
+        && let Ok(windows) = compositor.windows()
+        && let Some(window) = last_focused(&windows, |app_id| app.owns_window(app_id))
+        && compositor.focus(window).is_ok()
+    {
+        history::record_launch(&app.id);
+        return Ok(());
+    }
+    launch_app(app)
+}
+
+// The window with a matching app ID that had the focus last. Windows without
+// a focus order count as focused the longest ago; among those the
+// compositor's order decides.
+fn last_focused(windows: &[Window], matches: impl Fn(&str) -> bool) -> Option<&Window> {
+    windows
+        .iter()
+        .filter(|window| matches(&window.app_id))
+        .min_by_key(|window| window.focus_order.unwrap_or(u32::MAX))
+}
+
+fn ipc_error(err: io::Error) -> String {
+    format!("Couldn't talk to the window manager: {}", err)
+}
+
+fn connect(socket: &Path) -> io::Result<UnixStream> {
+    let stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(IPC_TIMEOUT))?;
+    stream.set_write_timeout(Some(IPC_TIMEOUT))?;
+    Ok(stream)
+}
+
+// Messages are the magic string, the payload length and message type as
+// native-endian u32s, then the payload; replies have the same framing.
+fn i3_request(socket: &Path, kind: u32, payload: &str) -> io::Result<String> {
+    let mut stream = connect(socket)?;
+    stream.write_all(&i3_message(kind, payload))?;
+
+    let mut header = [0; 14];
+    stream.read_exact(&mut header)?;
+    if &header[..6] != I3_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an i3 IPC reply",
+        ));
+    }
+    let length = u32::from_ne_bytes([header[6], header[7], header[8], header[9]]);
+    let mut reply = vec![0; length as usize];
+    stream.read_exact(&mut reply)?;
+    String::from_utf8(reply).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn i3_message(kind: u32, payload: &str) -> Vec<u8> {
+    let mut message = I3_MAGIC.to_vec();
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&kind.to_ne_bytes());
+    message.extend_from_slice(payload.as_bytes());
+    message
+}
+
+// Collects the leaves of the layout tree that hold a window: Wayland views
+// have a pid, X11 windows a window ID. Empty split containers have neither.
+fn i3_windows(node: &Value, workspace: Option<&str>, windows: &mut Vec<Window>) {
+    let workspace = match node["type"].as_str() {
+        Some("workspace") => node["name"].as_str(),
+        _ => workspace,
+    };
+
+    let children: Vec<&Value> = ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node[key].as_array())
+        .flatten()
+        .collect();
+    let is_window = matches!(node["type"].as_str(), Some("con" | "floating_con"))
+        && (node["pid"].is_u64() || node["window"].is_u64());
+
+    if children.is_empty() && is_window {
+        let app_id = node["app_id"]
+            .as_str()
+            .or_else(|| node["window_properties"]["class"].as_str())
+            .unwrap_or_default();
+        windows.push(Window {
+            id: node["id"].to_string(),
+            title: node["name"].as_str().unwrap_or_default().to_string(),
+            app_id: app_id.to_string(),
+            workspace: workspace.map(str::to_string),
+            focus_order: (node["focused"].as_bool() == Some(true)).then_some(0),
+        });
+    }
+    for child in children {
+        i3_windows(child, workspace, windows);
+    }
+}
+
+// Hyprland answers one request per connection and closes it.
+fn hyprland_request(socket: &Path, request: &str) -> io::Result<String> {
+    let mut stream = connect(socket)?;
+    stream.write_all(request.as_bytes())?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+#[derive(Deserialize)]
+struct HyprlandClient {
+    address: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    class: String,
+    #[serde(default = "mapped")]
+    mapped: bool,
+    workspace: Option<HyprlandWorkspace>,
+    // 0 for the focused window, then in order of last focus
+    #[serde(rename = "focusHistoryID", default = "unfocused")]
+    focus_history_id: i64,
+}
+
+#[derive(Deserialize)]
+struct HyprlandWorkspace {
+    name: String,
+}
+
+fn mapped() -> bool {
+    true
+}
+
+fn unfocused() -> i64 {
+    -1
+}
+
+fn hyprland_windows(clients: &str) -> Result<Vec<Window>, String> {
+    let clients: Vec<HyprlandClient> =
+        serde_json::from_str(clients).map_err(|err| err.to_string())?;
+    Ok(clients
+        .into_iter()
+        .filter(|client| client.mapped)
+        .map(|client| Window {
+            id: client.address,
+            title: client.title,
+            app_id: client.class,
+            workspace: client.workspace.map(|workspace| workspace.name),
+            focus_order: u32::try_from(client.focus_history_id).ok(),
+        })
+        .collect())
+}
+
+// The query of a window search (like "@term"), or None for other searches.
+pub fn window_query(text: &str) -> Option<&str> {
+    let text = text.trim_start();
+    PREFIXES
+        .iter()
+        .find_map(|prefix| text.strip_prefix(prefix))
+        .map(str::trim)
+}
+
+// The windows matching `query` by title or app, best first and then the most
+// recently focused first; an empty query lists them all. BitPop's own popup is
+// left out: it is the focused window while it's being searched from.
+pub fn search<'a>(windows: &'a [Window], query: &str) -> Vec<(i32, &'a Window)> {
+    let mut scored: Vec<(i32, &Window)> = windows
+        .iter()
+        .filter(|window| !window.is_focused() && window.app_id != APP_ID)
+        .filter_map(|window| {
+            let score = fuzzy::score(&window.title, query).max(fuzzy::score(&window.app_id, query));
+            Some((score?, window))
+        })
+        .collect();
+    scored.sort_by_key(|(score, window)| {
+        (
+            std::cmp::Reverse(*score),
+            window.focus_order.unwrap_or(u32::MAX),
+        )
+    });
+    scored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    // A compositor socket that answers each connection with `reply`, after
+    // handing the request it read to `requests`.
+    fn mock_server(
+        name: &str,
+        reply: impl Fn(&[u8]) -> Vec<u8> + Send + 'static,
+    ) -> (PathBuf, std::sync::mpsc::Receiver<Vec<u8>>) {
+        let socket = env::temp_dir().join(format!("bitpop-{}-{}.sock", name, std::process::id()));
+        let _ = fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        let (sender, requests) = std::sync::mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    break;
+                };
+                let mut request = vec![0; 4096];
+                let read = stream.read(&mut request).unwrap();
+                request.truncate(read);
+                let _ = stream.write_all(&reply(&request));
+                let _ = sender.send(request);
+            }
+        });
+        (socket, requests)
+    }
+
+    fn window(title: &str, app_id: &str, focus_order: Option<u32>) -> Window {
+        Window {
+            id: title.to_string(),
+            title: title.to_string(),
+            app_id: app_id.to_string(),
+            workspace: None,
+            focus_order,
+        }
+    }
+
+    #[test]
+    fn the_compositor_is_found_from_the_environment() {
+        let env = |vars: &[(&str, &str)]| {
+            let vars: HashMap<String, String> = vars
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            Compositor::from_env(|name| vars.get(name).cloned())
+        };
+
+        assert_eq!(
+            env(&[("SWAYSOCK", "/run/sway.sock"), ("I3SOCK", "/run/i3.sock")]),
+            Some(Compositor::I3(PathBuf::from("/run/sway.sock")))
+        );
+        assert_eq!(
+            env(&[("I3SOCK", "/run/i3.sock")]),
+            Some(Compositor::I3(PathBuf::from("/run/i3.sock")))
+        );
+        assert_eq!(
+            env(&[("HYPRLAND_INSTANCE_SIGNATURE", "abc")]),
+            Some(Compositor::Hyprland(PathBuf::from(
+                "/tmp/hypr/abc/.socket.sock"
+            )))
+        );
+        assert_eq!(env(&[]), None);
+    }
+
+    #[test]
+    fn i3_windows_are_listed_and_focused_over_ipc() {
+        let tree = r#"{"id":1,"type":"root","nodes":[{"id":2,"type":"output","nodes":[
+            {"id":3,"type":"workspace","name":"1","nodes":[
+                {"id":10,"type":"con","name":"~/src","app_id":"foot","pid":100,
+                 "focused":true,"nodes":[]},
+                {"id":11,"type":"con","name":null,"nodes":[
+                    {"id":12,"type":"con","name":"Inbox","window":4242,
+                     "window_properties":{"class":"thunderbird"},"nodes":[]}]}],
+             "floating_nodes":[
+                {"id":13,"type":"floating_con","name":"Calculator",
+                 "app_id":"org.gnome.Calculator","pid":101,"nodes":[]}]},
+            {"id":4,"type":"workspace","name":"2","nodes":[]}]}]}"#;
+        let (socket, requests) = mock_server("i3", move |request| {
+            let payload = &request[14..];
+            if payload.is_empty() {
+                i3_message(I3_GET_TREE, tree)
+            } else {
+                i3_message(I3_RUN_COMMAND, r#"[{"success":true}]"#)
+            }
+        });
+        let compositor = Compositor::I3(socket.clone());
+
+        let windows = compositor.windows().unwrap();
+        assert_eq!(
+            windows,
+            vec![
+                Window {
+                    id: "10".to_string(),
+                    title: "~/src".to_string(),
+                    app_id: "foot".to_string(),
+                    workspace: Some("1".to_string()),
+                    focus_order: Some(0),
+                },
+                Window {
+                    id: "12".to_string(),
+                    title: "Inbox".to_string(),
+                    app_id: "thunderbird".to_string(),
+                    workspace: Some("1".to_string()),
+                    focus_order: None,
+                },
+                Window {
+                    id: "13".to_string(),
+                    title: "Calculator".to_string(),
+                    app_id: "org.gnome.Calculator".to_string(),
+                    workspace: Some("1".to_string()),
+                    focus_order: None,
+                },
+            ]
+        );
+        assert_eq!(requests.recv().unwrap(), i3_message(I3_GET_TREE, ""));
+
+        compositor.focus(&windows[1]).unwrap();
+        assert_eq!(
+            requests.recv().unwrap(),
+            i3_message(I3_RUN_COMMAND, "[con_id=12] focus")
+        );
+
+        fs::remove_file(socket).unwrap();
+    }
+
+    #[test]
+    fn hyprland_windows_are_listed_and_focused_over_ipc() {
+        let clients = concat!(
+            r#"[{"address":"0x1","title":"~/src","class":"foot","mapped":true,"#,
+            r#""workspace":{"id":1,"name":"1"},"focusHistoryID":1},"#,
+            r#"{"address":"0x2","title":"Mozilla Firefox","class":"firefox","mapped":true,"#,
+            r#""workspace":{"id":2,"name":"web"},"focusHistoryID":0},"#,
+            r#"{"address":"0x3","title":"","class":"","mapped":false}]"#,
+        );
+        let (socket, requests) = mock_server("hyprland", move |request| {
+            if request == b"j/clients" {
+                clients.as_bytes().to_vec()
+            } else {
+                b"ok".to_vec()
+            }
+        });
+        let compositor = Compositor::Hyprland(socket.clone());
+
+        let windows = compositor.windows().unwrap();
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0].title, "~/src");
+        assert_eq!(windows[1].workspace.as_deref(), Some("web"));
+        assert_eq!(windows[0].focus_order, Some(1));
+        assert!(windows[1].is_focused());
+        assert_eq!(requests.recv().unwrap(), b"j/clients");
+
+        compositor.focus(&windows[0]).unwrap();
+        assert_eq!(
+            requests.recv().unwrap(),
+            b"dispatch focuswindow address:0x1"
+        );
+
+        fs::remove_file(socket).unwrap();
+    }
+
+    #[test]
+    fn windows_match_by_title_or_app() {
+        let windows = vec![
+            window("Mozilla Firefox", "firefox", Some(3)),
+            window("~/src/bitpop", "foot", None),
+            window("Inbox", "thunderbird", Some(1)),
+            window("BitPop", APP_ID, Some(0)),
+        ];
+        let titles = |query: &str| -> Vec<&str> {
+            search(&windows, query)
+                .into_iter()
+                .map(|(_, window)| window.title.as_str())
+                .collect()
+        };
+
+        assert_eq!(window_query(" @ fire"), Some("fire"));
+        assert_eq!(window_query("fire"), None);
+
+        assert_eq!(titles("foot"), vec!["~/src/bitpop"]);
+        assert_eq!(titles("bitpop"), vec!["~/src/bitpop"]);
+        assert_eq!(titles("thund"), vec!["Inbox"]);
+        // The popup itself isn't listed, and the others go by focus
+        assert_eq!(titles(""), vec!["Inbox", "Mozilla Firefox", "~/src/bitpop"]);
+        assert!(titles("bitpop").iter().all(|title| *title != "BitPop"));
+    }
+
+    #[test]
+    fn the_last_focused_window_of_an_app_is_chosen() {
+        let is_foot = |app_id: &str| app_id == "foot";
+        let windows = vec![
+            window("first", "foot", None),
+            window("Inbox", "thunderbird", Some(1)),
+            window("recent", "foot", Some(2)),
+            window("older", "foot", Some(5)),
+        ];
+        assert_eq!(last_focused(&windows, is_foot).unwrap().title, "recent");
+
+        // Without a focus history, the compositor's order
+        let windows = vec![
+            window("first", "foot", None),
+            window("second", "foot", None),
+        ];
+        assert_eq!(last_focused(&windows, is_foot).unwrap().title, "first");
+        assert!(last_focused(&windows, |app_id| app_id == "firefox").is_none());
+    }
+}